@@ -1,100 +1,458 @@
 // Accelerator
-use crate::{BoundingBox, Hittable, Float, Ray, HitRecord};
-use rand;
-use std::cmp::Ordering;
-use std::rc::Rc;
+use crate::{BoundingBox, Float, HitRecord, HitSamples, Hittable, Ray, Vec3};
+use std::fmt;
+use std::sync::Arc;
 
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: Float = 0.125;
+// Deepest level a leaf may sit at, which bounds the traversal stack. Splits that peel off
+// few primitives at a time, as in clusters of vastly different sizes, end in one larger leaf.
+const MAX_DEPTH: usize = 64;
+
+// Node of the flattened BVH. Interior nodes keep their first child right after
+// themselves and the index of the second child in `offset`, leaves keep the range
+// of their primitives as `offset..offset + count`.
+#[derive(Copy, Clone)]
 pub struct BoundingBoxNode {
     pub bbox: BoundingBox,
-    pub left: Option<Rc<dyn Hittable>>,
-    pub right: Option<Rc<dyn Hittable>>,
+    pub offset: usize,
+    pub count: usize,
+    pub axis: u8,
+}
+
+impl BoundingBoxNode {
+    pub fn zero() -> BoundingBoxNode {
+        BoundingBoxNode {
+            bbox: BoundingBox::zero(),
+            offset: 0,
+            count: 0,
+            axis: 0,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+pub struct Bvh {
+    pub nodes: Vec<BoundingBoxNode>,
+    pub primitives: Vec<Arc<dyn Hittable>>,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bbox: BoundingBox,
+    centroid: Vec3,
+}
+
+#[derive(Copy, Clone)]
+struct Bin {
+    bbox: Option<BoundingBox>,
+    count: usize,
+}
+
+fn merge(a: Option<BoundingBox>, b: &BoundingBox) -> BoundingBox {
+    match a {
+        Some(a) => BoundingBox::surrounding_box(&a, b),
+        None => *b,
+    }
+}
+
+fn bounds_of(prims: &[BuildPrimitive]) -> BoundingBox {
+    let mut bbox = prims[0].bbox;
+    for p in prims[1..].iter() {
+        bbox = BoundingBox::surrounding_box(&bbox, &p.bbox);
+    }
+    bbox
 }
 
-pub fn sort_hitables(first: &dyn Hittable, second: &dyn Hittable, axis: u8) -> Ordering {
-    let first_box: BoundingBox = first.bounding_box();
-    let second_box: BoundingBox = second.bounding_box();
-    if (&first_box.min)[axis] - (&second_box.min)[axis] < 0.0 {
-        return Ordering::Less;
+fn centroid_bounds_of(prims: &[BuildPrimitive]) -> BoundingBox {
+    let mut bbox = BoundingBox::new(prims[0].centroid, prims[0].centroid);
+    for p in prims[1..].iter() {
+        bbox = bbox.surrounding_point(&p.centroid);
+    }
+    bbox
+}
+
+fn bin_index(centroid: &Vec3, bounds: &BoundingBox, axis: u8) -> usize {
+    let lo = (&bounds.min)[axis];
+    let extent = (&bounds.max)[axis] - lo;
+    let b = ((centroid[axis] - lo) / extent * BIN_COUNT as Float) as usize;
+    if b >= BIN_COUNT {
+        BIN_COUNT - 1
     } else {
-        return Ordering::Greater;
-    }
-}
-
-impl Hittable for BoundingBoxNode {
-    fn hit(&self, r: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord) -> bool {
-        // false
-        let mut left_rec = HitRecord::new();
-        let mut right_rec = HitRecord::new();
-        let left_hitted = self
-            .left
-            .as_ref()
-            .unwrap()
-            .as_ref()
-            .hit(r, t_min, t_max, &mut left_rec);
-        let right_hitted =
-            self.right
-                .as_ref()
-                .unwrap()
-                .as_ref()
-                .hit(r, t_min, t_max, &mut right_rec);
-        if left_hitted && right_hitted {
-            if left_rec.t < right_rec.t {
-                *rec = left_rec.copy();
+        b
+    }
+}
+
+impl Bvh {
+    pub fn new(hitables: &[Arc<dyn Hittable>]) -> Bvh {
+        let mut prims: Vec<BuildPrimitive> = hitables
+            .iter()
+            .enumerate()
+            .map(|(index, h)| {
+                let bbox = h.bounding_box();
+                BuildPrimitive {
+                    index,
+                    centroid: bbox.centroid(),
+                    bbox,
+                }
+            })
+            .collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * hitables.len()),
+            primitives: Vec::with_capacity(hitables.len()),
+        };
+        if !prims.is_empty() {
+            bvh.build(hitables, &mut prims[..], 0);
+        }
+        bvh
+    }
+
+    fn push_leaf(
+        &mut self,
+        hitables: &[Arc<dyn Hittable>],
+        prims: &[BuildPrimitive],
+        bbox: BoundingBox,
+    ) {
+        self.nodes.push(BoundingBoxNode {
+            bbox,
+            offset: self.primitives.len(),
+            count: prims.len(),
+            axis: 0,
+        });
+        for p in prims.iter() {
+            self.primitives.push(hitables[p.index].clone());
+        }
+    }
+
+    // Finds the cheapest binned SAH split as (axis, bin, cost), None when all centroids coincide
+    fn find_split(
+        prims: &[BuildPrimitive],
+        bbox: &BoundingBox,
+        centroids: &BoundingBox,
+    ) -> Option<(u8, usize, Float)> {
+        let mut best: Option<(u8, usize, Float)> = None;
+        let area = bbox.surface_area();
+        for axis in 0..3u8 {
+            if (&centroids.max)[axis] - (&centroids.min)[axis] <= 0.0 {
+                continue;
+            }
+            let mut bins = [Bin {
+                bbox: None,
+                count: 0,
+            }; BIN_COUNT];
+            for p in prims.iter() {
+                let b = bin_index(&p.centroid, centroids, axis);
+                bins[b].count += 1;
+                bins[b].bbox = Some(merge(bins[b].bbox, &p.bbox));
+            }
+            // Sweep from the right to know the cost of everything past each split plane
+            let mut right_area = [0.0; BIN_COUNT];
+            let mut right_count = [0; BIN_COUNT];
+            let mut acc: Option<BoundingBox> = None;
+            let mut count = 0;
+            for i in (1..BIN_COUNT).rev() {
+                if let Some(b) = &bins[i].bbox {
+                    acc = Some(merge(acc, b));
+                }
+                count += bins[i].count;
+                right_count[i] = count;
+                right_area[i] = acc.map_or(0.0, |b| b.surface_area());
+            }
+            let mut acc: Option<BoundingBox> = None;
+            let mut count = 0;
+            for i in 0..BIN_COUNT - 1 {
+                if let Some(b) = &bins[i].bbox {
+                    acc = Some(merge(acc, b));
+                }
+                count += bins[i].count;
+                if count == 0 || right_count[i + 1] == 0 {
+                    continue;
+                }
+                let left_area = acc.map_or(0.0, |b| b.surface_area());
+                let cost = TRAVERSAL_COST
+                    + (count as Float * left_area
+                        + right_count[i + 1] as Float * right_area[i + 1])
+                        / area;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, i, cost));
+                }
+            }
+        }
+        best
+    }
+
+    fn build(
+        &mut self,
+        hitables: &[Arc<dyn Hittable>],
+        prims: &mut [BuildPrimitive],
+        depth: usize,
+    ) {
+        let bbox = bounds_of(prims);
+        let count = prims.len();
+        if count == 1 || depth == MAX_DEPTH {
+            self.push_leaf(hitables, prims, bbox);
+            return;
+        }
+        let centroids = centroid_bounds_of(prims);
+        let (axis, mid) = match Bvh::find_split(prims, &bbox, &centroids) {
+            Some((_, _, cost)) if count <= MAX_LEAF_SIZE && cost >= count as Float => {
+                self.push_leaf(hitables, prims, bbox);
+                return;
+            }
+            Some((axis, bin, _)) => {
+                let mut mid = 0;
+                for i in 0..count {
+                    if bin_index(&prims[i].centroid, &centroids, axis) <= bin {
+                        prims.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+                (axis, mid)
+            }
+            None if count <= MAX_LEAF_SIZE => {
+                self.push_leaf(hitables, prims, bbox);
+                return;
+            }
+            // Every primitive shares the same centroid, any halving is as good as another
+            None => (centroids.longest_axis(), count / 2),
+        };
+        let index = self.nodes.len();
+        self.nodes.push(BoundingBoxNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis,
+        });
+        let (left, right) = prims.split_at_mut(mid);
+        self.build(hitables, left, depth + 1);
+        self.nodes[index].offset = self.nodes.len();
+        self.build(hitables, right, depth + 1);
+    }
+}
+
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    pub max_depth: usize,
+    pub max_leaf_size: usize,
+    pub sah_cost: Float,
+}
+
+impl BvhStats {
+    pub fn average_leaf_size(&self) -> Float {
+        if self.leaves == 0 {
+            return 0.0;
+        }
+        self.primitives as Float / self.leaves as Float
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BVH with {} nodes, {} leaves and {} primitives\n Max depth: {}\n Leaf size: {:.2} average, {} max\n SAH cost: {:.2}",
+            self.nodes,
+            self.leaves,
+            self.primitives,
+            self.max_depth,
+            self.average_leaf_size(),
+            self.max_leaf_size,
+            self.sah_cost
+        )
+    }
+}
+
+impl Bvh {
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            nodes: self.nodes.len(),
+            leaves: 0,
+            primitives: self.primitives.len(),
+            max_depth: 0,
+            max_leaf_size: 0,
+            sah_cost: 0.0,
+        };
+        if self.nodes.is_empty() {
+            return stats;
+        }
+        let root_area = self.nodes[0].bbox.surface_area();
+        let mut stack: Vec<(usize, usize)> = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let relative_area = if root_area > 0.0 {
+                node.bbox.surface_area() / root_area
+            } else {
+                1.0
+            };
+            stats.max_depth = stats.max_depth.max(depth);
+            if node.is_leaf() {
+                stats.leaves += 1;
+                stats.max_leaf_size = stats.max_leaf_size.max(node.count);
+                stats.sah_cost += relative_area * node.count as Float;
             } else {
-                *rec = right_rec.copy();
+                stats.sah_cost += relative_area * TRAVERSAL_COST;
+                stack.push((index + 1, depth + 1));
+                stack.push((node.offset, depth + 1));
             }
-            return true;
-        } else if left_hitted {
-            *rec = left_rec.copy();
-            return true;
-        } else if right_hitted {
-            *rec = right_rec.copy();
-            return true;
-        } else {
+        }
+        stats
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        if self.nodes.is_empty() {
             return false;
         }
+        let inv_dir = Vec3::new(
+            1.0 / r.direction.x,
+            1.0 / r.direction.y,
+            1.0 / r.direction.z,
+        );
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+        let mut closest = t_max;
+        let mut hitted = false;
+        // Every interior node on the path to a leaf defers at most one child
+        let mut stack: [usize; MAX_DEPTH] = [0; MAX_DEPTH];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit_inverse(&r.origin, &inv_dir, t_min, closest) {
+                if node.is_leaf() {
+                    for primitive in self.primitives[node.offset..node.offset + node.count].iter() {
+                        if primitive.hit(r, t_min, closest, rec, samples) {
+                            hitted = true;
+                            closest = rec.t;
+                        }
+                    }
+                } else {
+                    // Visit the child on the near side of the split axis first
+                    if dir_is_neg[node.axis as usize] {
+                        stack[top] = current + 1;
+                        current = node.offset;
+                    } else {
+                        stack[top] = node.offset;
+                        current += 1;
+                    }
+                    top += 1;
+                    continue;
+                }
+            }
+            if top == 0 {
+                break;
+            }
+            top -= 1;
+            current = stack[top];
+        }
+        hitted
     }
 
     fn bounding_box(&self) -> BoundingBox {
-        BoundingBox::surrounding_box(
-            &self.left.clone().unwrap().as_ref().bounding_box(),
-            &self.right.clone().unwrap().as_ref().bounding_box(),
-        )
+        match self.nodes.first() {
+            Some(node) => node.bbox,
+            None => BoundingBox::zero(),
+        }
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.primitives.iter().map(|p| p.primitive_count()).sum()
     }
 }
-impl BoundingBoxNode {
-    pub fn zero() -> BoundingBoxNode {
-        BoundingBoxNode {
-            bbox: BoundingBox::zero(),
-            left: None,
-            right: None,
-        }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BaseMaterial, ConstantTexture, Material, Pcg32, Sphere};
+
+    fn material() -> Arc<dyn BaseMaterial> {
+        Arc::new(Material {
+            albedo: Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))),
+        })
+    }
+
+    fn random_vector(rng: &mut Pcg32, scale: Float) -> Vec3 {
+        Vec3::new(
+            (rng.uniform() - 0.5) * scale,
+            (rng.uniform() - 0.5) * scale,
+            (rng.uniform() - 0.5) * scale,
+        )
     }
 
-    pub fn new(hitables: &mut [Rc<dyn Hittable>]) -> BoundingBoxNode {
-        let axis: u8 = 2 * rand::random::<Float>() as u8;
-        hitables.sort_by(|a, b| sort_hitables(a.as_ref(), b.as_ref(), axis));
-        let count = hitables.len();
-        let left: Rc<dyn Hittable>;
-        let right: Rc<dyn Hittable>;
-        if count == 1 {
-            left = hitables[0].clone();
-            right = hitables[0].clone();
-        } else if count == 2 {
-            left = hitables[0].clone();
-            right = hitables[1].clone();
+    fn closest_hit(hittable: &dyn Hittable, r: &Ray) -> Option<Float> {
+        let mut rec = HitRecord::new();
+        if hittable.hit(r, 0.001, Float::MAX, &mut rec, &mut HitSamples::new(0.5)) {
+            Some(rec.t)
         } else {
-            let half: usize = count / 2;
-            left = Rc::new(BoundingBoxNode::new(&mut hitables[0..half]));
-            right = Rc::new(BoundingBoxNode::new(&mut hitables[half..]));
+            None
         }
-        BoundingBoxNode {
-            left: Some(left.clone()),
-            right: Some(right.clone()),
-            bbox: BoundingBox::surrounding_box(
-                &left.as_ref().bounding_box(),
-                &right.as_ref().bounding_box(),
-            ),
+    }
+
+    #[test]
+    fn bvh_hits_match_a_linear_search() {
+        let mut rng = Pcg32::new(7, 0);
+        let mat = material();
+        let spheres: Vec<Arc<dyn Hittable>> = (0..200)
+            .map(|_| {
+                let radius = 0.05 + rng.uniform() * 0.3;
+                let center = random_vector(&mut rng, 10.0);
+                Arc::new(Sphere::new(mat.clone(), radius, center)) as Arc<dyn Hittable>
+            })
+            .collect();
+        let bvh = Bvh::new(&spheres);
+        assert_eq!(bvh.primitive_count(), spheres.len());
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random_vector(&mut rng, 14.0);
+            let target = random_vector(&mut rng, 10.0);
+            let r = Ray::new(origin, (target - origin).normalize(), 0.0);
+            let expected = closest_hit(&&spheres[..], &r);
+            assert_eq!(closest_hit(&bvh, &r), expected);
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn bvh_depth_is_limited() {
+        // Every split can only peel off the largest of these spheres
+        let mat = material();
+        let spheres: Vec<Arc<dyn Hittable>> = (0..300)
+            .map(|i| {
+                let x = (2.0 as Float).powi(i);
+                let center = Vec3::new(x, 0.0, 0.0);
+                Arc::new(Sphere::new(mat.clone(), x * 0.25, center)) as Arc<dyn Hittable>
+            })
+            .collect();
+        let bvh = Bvh::new(&spheres);
+        assert!(bvh.stats().max_depth <= MAX_DEPTH + 1);
+        for (i, sphere) in spheres.iter().enumerate() {
+            let center = sphere.bounding_box().centroid();
+            let height = (2.0 as Float).powi(i as i32);
+            let r = Ray::new(
+                center + Vec3::new(0.0, height, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                0.0,
+            );
+            let t = closest_hit(&bvh, &r).unwrap();
+            assert!((t / height - 0.75).abs() < 1e-9);
         }
     }
 }
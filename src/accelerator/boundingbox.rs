@@ -1,5 +1,7 @@
 // Bounding box
-use crate::{max, min, Vec3};
+use crate::{max, min, Float, Ray, Vec3};
+
+#[derive(Copy, Clone)]
 pub struct BoundingBox {
     pub min: Vec3,
     pub max: Vec3,
@@ -26,6 +28,86 @@ impl BoundingBox {
             y: max(b1.max.y, b2.max.y),
             z: max(b1.max.z, b2.max.z),
         };
-        return BoundingBox::new(small, big);
+        BoundingBox::new(small, big)
+    }
+
+    pub fn surrounding_point(&self, p: &Vec3) -> BoundingBox {
+        BoundingBox::surrounding_box(self, &BoundingBox::new(*p, *p))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        &(self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(&self) -> u8 {
+        let d = self.extent();
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let d = self.extent();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: Float, t_max: Float) -> bool {
+        let inv_dir = Vec3::new(
+            1.0 / r.direction.x,
+            1.0 / r.direction.y,
+            1.0 / r.direction.z,
+        );
+        self.hit_inverse(&r.origin, &inv_dir, t_min, t_max)
+    }
+
+    // Part of the ray between t_min and t_max that lies inside the box
+    pub fn ray_interval(&self, r: &Ray, t_min: Float, t_max: Float) -> Option<(Float, Float)> {
+        let inv_dir = Vec3::new(
+            1.0 / r.direction.x,
+            1.0 / r.direction.y,
+            1.0 / r.direction.z,
+        );
+        self.interval_inverse(&r.origin, &inv_dir, t_min, t_max)
+    }
+
+    // Slab test with the reciprocal of the ray direction computed once per ray
+    pub fn hit_inverse(&self, origin: &Vec3, inv_dir: &Vec3, t_min: Float, t_max: Float) -> bool {
+        self.interval_inverse(origin, inv_dir, t_min, t_max)
+            .is_some()
+    }
+
+    fn interval_inverse(
+        &self,
+        origin: &Vec3,
+        inv_dir: &Vec3,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, Float)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3u8 {
+            let o = (&origin)[axis];
+            let inv = (&inv_dir)[axis];
+            let mut near = ((&self.min)[axis] - o) * inv;
+            let mut far = ((&self.max)[axis] - o) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // Written so that NaN from 0 * inf keeps the current interval
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
     }
 }
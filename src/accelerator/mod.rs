@@ -1,4 +1,5 @@
 // Accelerator modules
+#[allow(clippy::module_inception)]
 mod accelerator;
 mod boundingbox;
 
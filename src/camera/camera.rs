@@ -1,5 +1,6 @@
 // Camera
-use crate::{Float, Ray, Vec3};
+use crate::{sample_unit_disk, Float, Ray, Sampler, Vec3};
+use std::fmt;
 
 #[derive(Copy, Clone)]
 pub struct Camera {
@@ -10,6 +11,8 @@ pub struct Camera {
     pub aperture: Float,
     pub angle: Float,
     pub focus_distance: Float,
+    pub shutter_open: Float,
+    pub shutter_close: Float,
 }
 impl Camera {
     pub fn new(position: Vec3, lookat: Vec3, up: Vec3) -> Camera {
@@ -17,30 +20,22 @@ impl Camera {
         let right = up.cross(&direction).normalize();
         Camera {
             position,
-            direction: direction.clone(),
+            direction,
             aperture: 0.0,
-            right: right.clone(),
+            right,
             down: right.cross(&direction),
             angle: 55f64.to_radians(),
             focus_distance: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
     pub fn get_up(&self) -> Vec3 {
         self.down.inverse()
     }
-    pub fn to_string(&self) -> String {
-        format!(
-            "Camera:\n Position: {}\n Direction: {}\n Down: {}\n Right: {}",
-            self.position.to_string(),
-            self.direction.to_string(),
-            self.down.to_string(),
-            self.right.to_string()
-        )
-    }
-
     pub fn print(&self) {
-        println!("{}", self.to_string());
+        println!("{}", self);
     }
 
     pub fn get_down(&self) -> Vec3 {
@@ -67,11 +62,24 @@ impl Camera {
         self.angle = value.to_radians()
     }
 
-    pub fn get_origin_ray(&self, aspect_ratio: Float, x: Float, y: Float) -> Ray {
-        let rand_dir = &Vec3::random_in_disk() * self.aperture;
-        let offset: Vec3 = (&self.right * rand_dir.x) + (&self.down * rand_dir.y);
+    pub fn set_shutter(&mut self, open: Float, close: Float) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    pub fn get_origin_ray(
+        &self,
+        aspect_ratio: Float,
+        x: Float,
+        y: Float,
+        sampler: &mut dyn Sampler,
+    ) -> Ray {
+        let (lens_x, lens_y) = sample_unit_disk(sampler.get_2d());
+        let offset: Vec3 =
+            self.right * (lens_x * self.aperture) + self.down * (lens_y * self.aperture);
         let dir = self.get_origin_direction(aspect_ratio, x, y, offset);
-        let r: Ray = Ray::new(self.position + offset, dir, 0.0);
+        let time = self.shutter_open + sampler.get_1d() * (self.shutter_close - self.shutter_open);
+        let r: Ray = Ray::new(self.position + offset, dir, time);
         // r.direction.print();
         r
     }
@@ -86,26 +94,85 @@ impl Camera {
         let mut dx: Float = 0.0;
         let mut dy: Float = 0.0;
         let u_right = self.upper_right(aspect_ratio, &mut dx, &mut dy);
-        let dir = u_right + (self.right * (u * dx)) + (&self.down * (v * dy));
-        let v = (&dir - &self.position) - offset;
+        // The image plane spans the field of view at the focus distance
+        let dir = u_right + (self.right * (u * dx) + self.down * (v * dy)) * self.focus_distance;
+        let v = dir - self.position - offset;
         v.normalize()
     }
 
     pub fn upper_right(&self, aspect_ratio: Float, dx: &mut Float, dy: &mut Float) -> Vec3 {
         *dx = self.screen_width(aspect_ratio);
         *dy = self.screen_height();
-        let result = self.position + (&self.direction.normalize() * self.focus_distance)
-            - (&(&self.right * (*dx / 2.0)) * self.focus_distance)
-            - (&(&self.down * (*dy / 2.0)) * self.focus_distance);
-        result
+        self.position + self.direction.normalize() * self.focus_distance
+            - self.right * (*dx / 2.0) * self.focus_distance
+            - self.down * (*dy / 2.0) * self.focus_distance
     }
 
     pub fn screen_width(&self, aspect_ratio: Float) -> Float {
-        return self.screen_height() * aspect_ratio;
+        self.screen_height() * aspect_ratio
     }
 
     pub fn screen_height(&self) -> Float {
         let theta = self.angle;
-        return 2.0 * (theta / 2.0).tan();
+        2.0 * (theta / 2.0).tan()
+    }
+}
+
+impl fmt::Display for Camera {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Camera:\n Position: {}\n Direction: {}\n Down: {}\n Right: {}",
+            self.position,
+            self.direction,
+            self.down,
+            self.right
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-12, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn focus_distance_keeps_the_framing() {
+        let mut camera = Camera::new(
+            Vec3::new(1.0, 2.0, -5.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        camera.set_angle(40.0);
+        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.3, 0.8)];
+        let directions: Vec<Vec3> = corners
+            .iter()
+            .map(|(u, v)| camera.get_origin_direction(2.0, *u, *v, Vec3::zero()))
+            .collect();
+        for focus_distance in [0.25, 4.0, 37.0].iter() {
+            camera.focus_distance = *focus_distance;
+            for ((u, v), direction) in corners.iter().zip(directions.iter()) {
+                let offset = Vec3::zero();
+                assert_close(camera.get_origin_direction(2.0, *u, *v, offset), *direction);
+            }
+        }
+    }
+
+    #[test]
+    fn center_looks_at_the_target() {
+        let mut camera = Camera::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        camera.focus_distance = 3.0;
+        let offset = Vec3::zero();
+        assert_close(
+            camera.get_origin_direction(1.5, 0.5, 0.5, offset),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
     }
 }
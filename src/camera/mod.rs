@@ -1,3 +1,4 @@
 // Camera mod
+#[allow(clippy::module_inception)]
 mod camera;
 pub use camera::*;
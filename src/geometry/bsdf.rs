@@ -0,0 +1,362 @@
+// Scattering functions
+use crate::{
+    fresnel_dielectric, sample_unit_sphere, schlick, Float, HitRecord, MicrofacetDielectric,
+    MicrofacetReflection, PrincipledBxdf, Vec3,
+};
+use std::f64::consts::PI;
+use std::ops::BitOr;
+
+// Kinds of scattering a BxDF can do, combined with |
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BsdfFlags(u8);
+
+impl BsdfFlags {
+    pub const REFLECTION: BsdfFlags = BsdfFlags(1);
+    pub const TRANSMISSION: BsdfFlags = BsdfFlags(2);
+    pub const DIFFUSE: BsdfFlags = BsdfFlags(4);
+    pub const GLOSSY: BsdfFlags = BsdfFlags(8);
+    // Scattering into a few discrete directions, which eval and pdf cannot find
+    pub const SPECULAR: BsdfFlags = BsdfFlags(16);
+
+    pub fn contains(&self, other: BsdfFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.contains(BsdfFlags::SPECULAR)
+    }
+
+    // Whether some of the scattering can be evaluated towards a light
+    pub fn is_non_specular(&self) -> bool {
+        self.0 & (BsdfFlags::DIFFUSE.0 | BsdfFlags::GLOSSY.0) != 0
+    }
+}
+
+impl BitOr for BsdfFlags {
+    type Output = BsdfFlags;
+
+    fn bitor(self, other: BsdfFlags) -> BsdfFlags {
+        BsdfFlags(self.0 | other.0)
+    }
+}
+
+// Direction picked by a BxDF with the BSDF times the cosine towards it, and the density per
+// unit solid angle it was picked with, or its probability for specular samples
+pub struct BsdfSample {
+    pub wi: Vec3,
+    pub f: Vec3,
+    pub pdf: Float,
+    pub flags: BsdfFlags,
+}
+
+// Scattering in the shading frame, where the normal is +z and wo points away from the surface.
+// Values include the cosine of wi with the normal so that phase functions fit in as well.
+pub trait Bxdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3;
+    // uc picks between lobes and u the direction within the lobe
+    fn sample(&self, wo: &Vec3, uc: Float, u: (Float, Float)) -> Option<BsdfSample>;
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float;
+    fn flags(&self) -> BsdfFlags;
+}
+
+// Orthonormal frame around the shading normal, local directions have the normal as +z
+pub struct Frame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+// The BxDFs that materials place at hit points, held by value so shading allocates nothing
+pub enum BuiltinBxdf {
+    LambertianReflection(LambertianReflection),
+    FuzzyReflection(FuzzyReflection),
+    SpecularDielectric(SpecularDielectric),
+    IsotropicPhase(IsotropicPhase),
+    MicrofacetReflection(MicrofacetReflection),
+    MicrofacetDielectric(MicrofacetDielectric),
+    Principled(PrincipledBxdf),
+}
+
+// A BxDF placed at a hit point, taking and returning directions in world space
+pub struct Bsdf {
+    pub frame: Frame,
+    pub geometric_normal: Option<Vec3>,
+    pub bxdf: BuiltinBxdf,
+}
+
+// Ideal diffuse reflection
+pub struct LambertianReflection {
+    pub albedo: Vec3,
+}
+
+// Mirror reflection blurred by a random offset on a sphere of radius fuzz. The offset has no
+// density to evaluate, so the lobe counts as specular. It is added to the unit mirror direction,
+// where the original Metal offset the reflected ray direction as it came, so fuzz no longer
+// depends on the length of the direction a previous bounce left unnormalized.
+pub struct FuzzyReflection {
+    pub albedo: Vec3,
+    pub fuzz: Float,
+}
+
+// Smooth boundary between two dielectrics, eta is the ratio of the index on the far side over
+// the one on the side of the normal
+pub struct SpecularDielectric {
+    pub eta: Float,
+    pub tint: Vec3,
+    pub schlick: bool,
+}
+
+// Phase function scattering equally into every direction
+pub struct IsotropicPhase {
+    pub albedo: Vec3,
+}
+
+impl Frame {
+    pub fn new(normal: Vec3) -> Frame {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Frame {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
+
+    pub fn to_world(&self, v: &Vec3) -> Vec3 {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+}
+
+impl Bsdf {
+    pub fn new(rec: &HitRecord, bxdf: BuiltinBxdf) -> Bsdf {
+        Bsdf {
+            frame: Frame::new(rec.normal.unwrap()),
+            geometric_normal: rec.geometric_normal,
+            bxdf,
+        }
+    }
+
+    // Whether wi lies on different sides of the shading and the geometric normal, where the
+    // shading frame alone would let light leak through the surface
+    fn crosses_surface(&self, wi: &Vec3) -> bool {
+        match &self.geometric_normal {
+            Some(normal) => wi.dot(normal) * wi.dot(&self.frame.normal) < 0.0,
+            None => false,
+        }
+    }
+
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if self.crosses_surface(wi) {
+            return Vec3::zero();
+        }
+        self.bxdf.eval(
+            &self.frame.to_local(&wo.normalize()),
+            &self.frame.to_local(&wi.normalize()),
+        )
+    }
+
+    pub fn sample(&self, wo: &Vec3, uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        let mut sample = self
+            .bxdf
+            .sample(&self.frame.to_local(&wo.normalize()), uc, u)?;
+        if sample.pdf <= 0.0 {
+            return None;
+        }
+        sample.wi = self.frame.to_world(&sample.wi);
+        if self.crosses_surface(&sample.wi) {
+            return None;
+        }
+        Some(sample)
+    }
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        if self.crosses_surface(wi) {
+            return 0.0;
+        }
+        self.bxdf.pdf(
+            &self.frame.to_local(&wo.normalize()),
+            &self.frame.to_local(&wi.normalize()),
+        )
+    }
+
+    pub fn flags(&self) -> BsdfFlags {
+        self.bxdf.flags()
+    }
+}
+
+impl BuiltinBxdf {
+    fn bxdf(&self) -> &dyn Bxdf {
+        match self {
+            BuiltinBxdf::LambertianReflection(bxdf) => bxdf,
+            BuiltinBxdf::FuzzyReflection(bxdf) => bxdf,
+            BuiltinBxdf::SpecularDielectric(bxdf) => bxdf,
+            BuiltinBxdf::IsotropicPhase(bxdf) => bxdf,
+            BuiltinBxdf::MicrofacetReflection(bxdf) => bxdf,
+            BuiltinBxdf::MicrofacetDielectric(bxdf) => bxdf,
+            BuiltinBxdf::Principled(bxdf) => bxdf,
+        }
+    }
+}
+
+impl Bxdf for BuiltinBxdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.bxdf().eval(wo, wi)
+    }
+
+    fn sample(&self, wo: &Vec3, uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        self.bxdf().sample(wo, uc, u)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        self.bxdf().pdf(wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        self.bxdf().flags()
+    }
+}
+
+pub fn reflect(wo: &Vec3, wm: &Vec3) -> Vec3 {
+    &(wm * (2.0 * wo.dot(wm))) - wo
+}
+
+// A point on the unit sphere around the normal tip gives a cosine weighted direction
+pub fn sample_cosine_hemisphere(u: (Float, Float)) -> Vec3 {
+    let direction = sample_unit_sphere(u) + Vec3::new(0.0, 0.0, 1.0);
+    if direction.sum_squares() > 1e-12 {
+        direction.normalize()
+    } else {
+        Vec3::new(0.0, 0.0, 1.0)
+    }
+}
+
+impl Bxdf for LambertianReflection {
+    fn eval(&self, _wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.albedo * (wi.z.max(0.0) / PI)
+    }
+
+    fn sample(&self, _wo: &Vec3, _uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        let wi = sample_cosine_hemisphere(u);
+        Some(BsdfSample {
+            f: self.albedo * (wi.z / PI),
+            pdf: wi.z / PI,
+            wi,
+            flags: BsdfFlags::DIFFUSE | BsdfFlags::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3) -> Float {
+        wi.z.max(0.0) / PI
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE | BsdfFlags::REFLECTION
+    }
+}
+
+impl Bxdf for FuzzyReflection {
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        Vec3::zero()
+    }
+
+    fn sample(&self, wo: &Vec3, _uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        let mirror = Vec3::new(-wo.x, -wo.y, wo.z);
+        let wi = (mirror + sample_unit_sphere(u) * self.fuzz).normalize();
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            f: self.albedo,
+            pdf: 1.0,
+            flags: BsdfFlags::SPECULAR | BsdfFlags::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> Float {
+        0.0
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::SPECULAR | BsdfFlags::REFLECTION
+    }
+}
+
+impl Bxdf for SpecularDielectric {
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // Reflects or refracts in proportion to the Fresnel reflectance
+    fn sample(&self, wo: &Vec3, uc: Float, _u: (Float, Float)) -> Option<BsdfSample> {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let eta = 1.0 / self.eta;
+        let cos_theta = wo.z.min(1.0);
+        let reflectance = if self.schlick {
+            schlick(cos_theta, eta)
+        } else {
+            fresnel_dielectric(cos_theta, eta)
+        };
+        match wo.inverse().refract(&normal, eta) {
+            Some(wi) if uc >= reflectance => Some(BsdfSample {
+                wi,
+                f: self.tint * (1.0 - reflectance),
+                pdf: 1.0 - reflectance,
+                flags: BsdfFlags::SPECULAR | BsdfFlags::TRANSMISSION,
+            }),
+            // Total internal reflection always reflects
+            refracted => {
+                let probability = if refracted.is_some() {
+                    reflectance
+                } else {
+                    1.0
+                };
+                Some(BsdfSample {
+                    wi: Vec3::new(-wo.x, -wo.y, wo.z),
+                    f: self.tint * probability,
+                    pdf: probability,
+                    flags: BsdfFlags::SPECULAR | BsdfFlags::REFLECTION,
+                })
+            }
+        }
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> Float {
+        0.0
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::SPECULAR | BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION
+    }
+}
+
+// No cosine term, the phase function applies inside the volume rather than on a surface
+impl Bxdf for IsotropicPhase {
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        self.albedo * (1.0 / (4.0 * PI))
+    }
+
+    fn sample(&self, _wo: &Vec3, _uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            wi: sample_unit_sphere(u),
+            f: self.albedo * (1.0 / (4.0 * PI)),
+            pdf: 1.0 / (4.0 * PI),
+            flags: BsdfFlags::DIFFUSE | BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION,
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> Float {
+        1.0 / (4.0 * PI)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE | BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION
+    }
+}
@@ -0,0 +1,66 @@
+// Opacity cutouts
+use crate::{BaseMaterial, Bsdf, Float, HitRecord, HitSamples, Ray, Texture, Vec3};
+use std::sync::Arc;
+
+// Lets rays, shadow rays included, pass through the parts of another material where the average
+// of the opacity texture's channels is below threshold. With stochastic set, the remaining
+// hits are kept with a probability equal to the opacity, so a threshold of 0 gives purely
+// stochastic transparency.
+pub struct Cutout {
+    pub material: Arc<dyn BaseMaterial>,
+    pub opacity: Arc<dyn Texture>,
+    pub threshold: Float,
+    pub stochastic: bool,
+}
+
+impl Cutout {
+    pub fn new(
+        material: Arc<dyn BaseMaterial>,
+        opacity: Arc<dyn Texture>,
+        threshold: Float,
+        stochastic: bool,
+    ) -> Cutout {
+        Cutout {
+            material,
+            opacity,
+            threshold,
+            stochastic,
+        }
+    }
+}
+
+impl BaseMaterial for Cutout {
+    fn to_string(&self) -> String {
+        format!(
+            "Cutout {} with threshold {}{} over {}",
+            self.opacity.to_string(),
+            self.threshold,
+            if self.stochastic { ", stochastic" } else { "" },
+            self.material.to_string()
+        )
+    }
+
+    fn bsdf(&self, rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        self.material.bsdf(rin, rec)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_cut_out(&self, u: Float, v: Float, p: &Vec3, samples: &mut HitSamples) -> bool {
+        let value = self.opacity.value(u, v, p);
+        let opacity = (value.x + value.y + value.z) / 3.0;
+        if opacity < self.threshold {
+            return true;
+        }
+        if self.stochastic && opacity < 1.0 && samples.get_1d() >= opacity {
+            return true;
+        }
+        self.material.is_cut_out(u, v, p, samples)
+    }
+}
@@ -0,0 +1,227 @@
+// Environments
+use crate::{clamp, luminance, Distribution2D, Float, Image, LightSample, Vec3};
+use std::f64::consts::PI;
+
+// Radiance arriving from infinitely far away when a ray leaves the scene
+pub trait Environment: Send + Sync {
+    fn radiance(&self, direction: &Vec3) -> Vec3;
+    // Environments that can pick directions towards their bright parts are sampled as lights
+    fn is_sampled(&self) -> bool {
+        false
+    }
+    fn sample(&self, _u: Float, _v: Float) -> Option<LightSample> {
+        None
+    }
+    // Density per unit solid angle with which sample picks direction
+    fn pdf(&self, _direction: &Vec3) -> Float {
+        0.0
+    }
+    fn to_string(&self) -> String;
+    fn print(&self) {
+        println!("{}", self.to_string());
+    }
+}
+
+pub struct ConstantEnvironment {
+    pub color: Vec3,
+}
+
+// How the zenith color of a gradient combines with its horizon color
+#[derive(Copy, Clone, PartialEq)]
+pub enum GradientBlend {
+    // Adds the zenith color in proportion to the height, so the horizon color is the floor
+    Add,
+    // Blends linearly from the horizon color below to the zenith color straight up
+    Mix,
+}
+
+// Sky varying with the height of the direction, from straight down to straight up
+pub struct GradientEnvironment {
+    pub horizon: Vec3,
+    pub zenith: Vec3,
+    pub blend: GradientBlend,
+}
+
+// Equirectangular map, columns go around the y axis and rows from +y down to -y
+pub struct EnvironmentMap {
+    pub image: Image,
+    pub intensity: Float,
+    // Radians around the y axis
+    pub rotation: Float,
+    distribution: Distribution2D,
+}
+
+impl ConstantEnvironment {
+    pub fn new(color: Vec3) -> ConstantEnvironment {
+        ConstantEnvironment { color }
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: &Vec3) -> Vec3 {
+        self.color
+    }
+
+    fn to_string(&self) -> String {
+        format!("Constant environment with color {}", self.color)
+    }
+}
+
+impl GradientBlend {
+    pub fn from_name(name: &str) -> Option<GradientBlend> {
+        match name {
+            "add" => Some(GradientBlend::Add),
+            "mix" => Some(GradientBlend::Mix),
+            _ => None,
+        }
+    }
+}
+
+impl GradientEnvironment {
+    pub fn new(horizon: Vec3, zenith: Vec3, blend: GradientBlend) -> GradientEnvironment {
+        GradientEnvironment {
+            horizon,
+            zenith,
+            blend,
+        }
+    }
+
+    // The original sky, white below brightening to light blue overhead
+    pub fn sky() -> GradientEnvironment {
+        GradientEnvironment::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.5, 0.7, 1.0),
+            GradientBlend::Add,
+        )
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let t = 0.5 * (direction.normalize().y + 1.0);
+        match self.blend {
+            GradientBlend::Add => self.horizon + self.zenith * t,
+            GradientBlend::Mix => self.horizon * (1.0 - t) + self.zenith * t,
+        }
+    }
+
+    fn to_string(&self) -> String {
+        let blend = match self.blend {
+            GradientBlend::Add => "adding",
+            GradientBlend::Mix => "mixing",
+        };
+        format!(
+            "Gradient environment {} {} to {}",
+            blend, self.zenith, self.horizon
+        )
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image, intensity: Float, rotation: Float) -> EnvironmentMap {
+        // The bilinear lookup blends each pixel with its neighbours, weighting pixels by the
+        // brightest of them keeps lookups from exceeding what sampling expects. Rows near the
+        // poles cover less solid angle and are picked less often.
+        let (width, height) = (image.width, image.height);
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as Float + 0.5) / height as Float).sin();
+            for x in 0..width {
+                let mut brightest: Float = 0.0;
+                for row in &image.pixels[y.saturating_sub(1)..(y + 2).min(height)] {
+                    for dx in [width - 1, 0, 1].iter() {
+                        brightest = brightest.max(luminance(&row[(x + dx) % width]));
+                    }
+                }
+                func.push(brightest * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, image.width, image.height);
+        EnvironmentMap {
+            image,
+            intensity,
+            rotation,
+            distribution,
+        }
+    }
+
+    // Image coordinates in [0, 1)^2 of a unit direction in world space
+    fn direction_to_uv(&self, direction: &Vec3) -> (Float, Float) {
+        let d = direction.rotate_y(-self.rotation);
+        let phi = d.z.atan2(d.x);
+        let theta = clamp(d.y, -1.0, 1.0).acos();
+        let u = (phi + PI) / (2.0 * PI);
+        (u - u.floor(), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: Float, v: Float) -> Vec3 {
+        let phi = 2.0 * PI * u - PI;
+        let theta = PI * v;
+        let sin_theta = theta.sin();
+        Vec3::new(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin()).rotate_y(self.rotation)
+    }
+
+    // Bilinear lookup, wrapping around horizontally and clamping at the poles
+    fn lookup(&self, u: Float, v: Float) -> Vec3 {
+        let (width, height) = (self.image.width, self.image.height);
+        let x = u * width as Float - 0.5;
+        let y = clamp(v * height as Float - 0.5, 0.0, (height - 1) as Float);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let x0 = (x0 as i64).rem_euclid(width as i64) as usize;
+        let x1 = (x0 + 1) % width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(height - 1);
+        let row0 = &self.image.pixels[y0];
+        let row1 = &self.image.pixels[y1];
+        let top = row0[x0] * (1.0 - fx) + row0[x1] * fx;
+        let bottom = row1[x0] * (1.0 - fx) + row1[x1] * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = self.direction_to_uv(&direction.normalize());
+        self.lookup(u, v) * self.intensity
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, u: Float, v: Float) -> Option<LightSample> {
+        let ((x, y), pdf_uv) = self.distribution.sample((u, v));
+        let sin_theta = (PI * y).sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // The map covers 2 pi by pi radians, and a pixel shrinks by sin theta towards the poles
+        let pdf = pdf_uv / (2.0 * PI * PI * sin_theta);
+        Some(LightSample {
+            direction: self.uv_to_direction(x, y),
+            distance: Float::INFINITY,
+            pdf,
+            radiance: self.lookup(x, y) * self.intensity,
+        })
+    }
+
+    fn pdf(&self, direction: &Vec3) -> Float {
+        let (u, v) = self.direction_to_uv(&direction.normalize());
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Environment map of {}x{} pixels with intensity {} rotated by {} degrees",
+            self.image.width,
+            self.image.height,
+            self.intensity,
+            self.rotation.to_degrees()
+        )
+    }
+}
@@ -1,15 +1,34 @@
 //Hitable
-use crate::{BaseMaterial, BoundingBox, Float, Ray, Vec3};
+use crate::{area_to_solid_angle, BaseMaterial, BoundingBox, Float, Light, Pcg32, Ray, Vec3};
 use std::f64::consts::PI;
-use std::rc::Rc;
+use std::fmt;
+use std::sync::Arc;
+
+// Intersections with one object a ray may pass through at cut out parts of its material
+const MAX_CUTOUT_LAYERS: usize = 4;
+// Distance along the ray to step past a cut out intersection before looking for the next
+const CUTOUT_OFFSET: Float = 1e-4;
 
 pub struct HitRecord {
     pub t: Float,
     pub p: Option<Vec3>,
+    // Shading normal, which materials and interpolated vertex normals may tilt away from the
+    // geometric normal of the surface
     pub normal: Option<Vec3>,
-    pub material: Option<Rc<dyn BaseMaterial>>,
+    pub geometric_normal: Option<Vec3>,
+    pub material: Option<Arc<dyn BaseMaterial>>,
     pub u: Float,
     pub v: Float,
+    // Derivatives of the surface point along u and v, for surfaces that know them
+    pub dpdu: Option<Vec3>,
+    pub dpdv: Option<Vec3>,
+    pub front_face: bool,
+}
+
+impl Default for HitRecord {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HitRecord {
@@ -17,52 +36,115 @@ impl HitRecord {
         HitRecord {
             p: None,
             normal: None,
+            geometric_normal: None,
             material: None,
             t: 0.0,
             u: 0.0,
             v: 0.0,
+            dpdu: None,
+            dpdv: None,
+            front_face: true,
         }
     }
     pub fn copy(&self) -> HitRecord {
         HitRecord {
-            p: match self.p {
-                Some(_p) => Some(_p.clone()),
-                _ => None,
-            },
-            normal: match self.normal {
-                Some(_p) => Some(_p.clone()),
-                _ => None,
-            },
-            material: match &self.material {
-                Some(_p) => Some(_p.clone()),
-                _ => None,
-            },
+            p: self.p,
+            normal: self.normal,
+            geometric_normal: self.geometric_normal,
+            material: self.material.clone(),
             t: self.t,
             u: self.u,
             v: self.v,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
+            front_face: self.front_face,
+        }
+    }
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
+        self.front_face = r.direction.dot(outward_normal) < 0.0;
+        self.normal = Some(if self.front_face {
+            *outward_normal
+        } else {
+            outward_normal.inverse()
+        });
+        self.geometric_normal = self.normal;
+    }
+    // Sets a shading normal on the same side as the geometric normal
+    pub fn set_shading_normal(&mut self, normal: &Vec3) {
+        let geometric = self.geometric_normal.unwrap();
+        self.normal = Some(if normal.dot(&geometric) < 0.0 {
+            normal.inverse()
+        } else {
+            *normal
+        });
+    }
+}
+
+// Uniform values for the random decisions a hit query makes, such as keeping a stochastic
+// cutout or the free flight distance in a medium. The first value comes from the integrator's
+// sampler, any further ones along the same ray from a generator seeded with it.
+pub struct HitSamples {
+    first: Option<Float>,
+    rng: Pcg32,
+}
+
+impl HitSamples {
+    pub fn new(u: Float) -> HitSamples {
+        HitSamples {
+            first: Some(u),
+            rng: Pcg32::new(u.to_bits(), 0),
+        }
+    }
+
+    pub fn get_1d(&mut self) -> Float {
+        match self.first.take() {
+            Some(u) => u,
+            None => self.rng.uniform(),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Object {
-    pub object: Box<dyn HittableObject>,
-    pub material: Rc<dyn BaseMaterial>,
+    pub object: Arc<dyn HittableObject>,
+    pub material: Arc<dyn BaseMaterial>,
 }
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord) -> bool;
+pub trait Hittable: Send + Sync {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool;
     fn bounding_box(&self) -> BoundingBox;
+    fn primitive_count(&self) -> usize {
+        1
+    }
+    // Emitters inside this hittable that the scene should sample directly
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        Vec::new()
+    }
 }
 
-impl Hittable for &[Rc<dyn Hittable>] {
-    fn hit(&self, r: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord) -> bool {
+impl Hittable for &[Arc<dyn Hittable>] {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
         let mut temp_record = HitRecord::new();
         let mut hitted: bool = false;
         let mut closest: Float = t_max;
         for i in 0..self.len() {
-            if self[i].hit(r, t_min, closest, &mut temp_record) {
+            if self[i].hit(r, t_min, closest, &mut temp_record, samples) {
                 hitted = true;
-                closest = (&temp_record).t;
+                closest = temp_record.t;
                 *rec = temp_record.copy();
             }
         }
@@ -70,7 +152,7 @@ impl Hittable for &[Rc<dyn Hittable>] {
     }
 
     fn bounding_box(&self) -> BoundingBox {
-        let first: Rc<dyn Hittable> = self[0].clone();
+        let first: Arc<dyn Hittable> = self[0].clone();
         let mut b_box = first.bounding_box();
         for i in 1..self.len() {
             b_box = BoundingBox::surrounding_box(&b_box, &self[i].bounding_box());
@@ -79,32 +161,98 @@ impl Hittable for &[Rc<dyn Hittable>] {
     }
 }
 
-impl Hittable for Object {
-    fn hit(&self, r: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord) -> bool {
-        let distance: Float = self.object.find_intersection(&r);
-        if distance > t_min && distance < t_max {
+fn hit_object(
+    object: &dyn HittableObject,
+    material: &Arc<dyn BaseMaterial>,
+    r: &Ray,
+    t_min: Float,
+    t_max: Float,
+    rec: &mut HitRecord,
+    samples: &mut HitSamples,
+) -> bool {
+    let mut distance: Float = object.find_intersection(r);
+    for _ in 0..MAX_CUTOUT_LAYERS {
+        if !(distance > t_min && distance < t_max) {
+            return false;
+        }
+        let point: Vec3 = r.point_at_length(distance);
+        let (u, v) = object.get_uv(&point);
+        if !material.is_cut_out(u, v, &point, samples) {
             rec.t = distance;
-            let point: Vec3 = r.point_at_length(distance);
-            rec.normal = Some((&self.object).get_normal_at(&point));
-            let (u, v) = (&self.object).get_uv(&point);
+            rec.set_face_normal(r, &object.get_geometric_normal_at(&point));
+            rec.set_shading_normal(&object.get_normal_at(&point));
             rec.u = u;
             rec.v = v;
-            rec.material = Some(self.material.clone());
+            let derivatives = object.get_uv_derivatives(&point);
+            rec.dpdu = derivatives.map(|d| d.0);
+            rec.dpdv = derivatives.map(|d| d.1);
+            rec.material = Some(material.clone());
             rec.p = Some(point);
             return true;
         }
-        false
+        // Continue just past the cut out hit to the next intersection with the same object
+        let start = distance + CUTOUT_OFFSET;
+        let beyond = Ray::new(r.point_at_length(start), r.direction, r.time);
+        let further = object.find_intersection(&beyond);
+        if further <= 0.0 {
+            return false;
+        }
+        distance = start + further;
+    }
+    false
+}
+
+impl Hittable for Object {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        hit_object(
+            self.object.as_ref(),
+            &self.material,
+            r,
+            t_min,
+            t_max,
+            rec,
+            samples,
+        )
     }
     fn bounding_box(&self) -> BoundingBox {
         self.object.get_bounding_box()
     }
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        if !self.material.is_emissive() {
+            return Vec::new();
+        }
+        vec![Arc::new(self.clone())]
+    }
 }
 
-pub trait HittableObject {
+pub trait HittableObject: Send + Sync {
     fn find_intersection(&self, r: &Ray) -> Float;
     fn get_bounding_box(&self) -> BoundingBox;
     fn get_normal_at(&self, v: &Vec3) -> Vec3;
+    // Normal of the actual surface, where get_normal_at may interpolate a smoother one
+    fn get_geometric_normal_at(&self, v: &Vec3) -> Vec3 {
+        self.get_normal_at(v)
+    }
     fn get_uv(&self, v: &Vec3) -> (Float, Float);
+    // Derivatives of the surface point along u and v at a point on the surface
+    fn get_uv_derivatives(&self, _v: &Vec3) -> Option<(Vec3, Vec3)> {
+        None
+    }
+    // Samples a point on the surface as seen from origin, along with its density per unit solid angle
+    fn sample(&self, _origin: &Vec3, _u: Float, _v: Float) -> Option<(Vec3, Float)> {
+        None
+    }
+    // Density per unit solid angle with which sample returns the given point
+    fn pdf(&self, _origin: &Vec3, _point: &Vec3) -> Float {
+        0.0
+    }
     fn to_string(&self) -> String;
     fn print(&self) {
         println!("{}", self.to_string());
@@ -117,26 +265,134 @@ pub struct Sphere {
     center: Vec3,
 }
 impl Sphere {
-    pub fn new(mat: Rc<dyn BaseMaterial>, radius: Float, center: Vec3) -> Object {
-        let s = Box::new(Sphere { radius, center });
+    // Shapes are built already wrapped in an Object with their material
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(mat: Arc<dyn BaseMaterial>, radius: Float, center: Vec3) -> Object {
+        let s = Arc::new(Sphere { radius, center });
         Object {
             object: s,
-            material: Rc::clone(&mat),
+            material: Arc::clone(&mat),
         }
     }
 }
 
+// Sphere moving in a straight line from center0 at time0 to center1 at time1, resting
+// at either end outside that interval
+#[derive(Clone)]
+pub struct MovingSphere {
+    pub radius: Float,
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: Float,
+    pub time1: Float,
+    pub material: Arc<dyn BaseMaterial>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        mat: Arc<dyn BaseMaterial>,
+        radius: Float,
+        center0: Vec3,
+        center1: Vec3,
+        time0: Float,
+        time1: Float,
+    ) -> MovingSphere {
+        MovingSphere {
+            radius,
+            center0,
+            center1,
+            time0,
+            time1,
+            material: mat,
+        }
+    }
+
+    pub fn center(&self, time: Float) -> Vec3 {
+        if time <= self.time0 || self.time1 <= self.time0 {
+            return self.center0;
+        }
+        if time >= self.time1 {
+            return self.center1;
+        }
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + (self.center1 - self.center0) * t
+    }
+
+    pub fn sphere_at(&self, time: Float) -> Sphere {
+        Sphere {
+            radius: self.radius,
+            center: self.center(time),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{}", self)
+    }
+}
+
+impl fmt::Display for MovingSphere {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Moving sphere with radius {} from {} at {} to {} at {}\nMaterial->\n {}",
+            self.radius,
+            self.center0,
+            self.time0,
+            self.center1,
+            self.time1,
+            self.material.to_string()
+        )
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        let sphere = self.sphere_at(r.time);
+        hit_object(&sphere, &self.material, r, t_min, t_max, rec, samples)
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        let start = Sphere {
+            radius: self.radius,
+            center: self.center0,
+        };
+        let end = Sphere {
+            radius: self.radius,
+            center: self.center1,
+        };
+        BoundingBox::surrounding_box(&start.get_bounding_box(), &end.get_bounding_box())
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        if !self.material.is_emissive() {
+            return Vec::new();
+        }
+        vec![Arc::new(self.clone())]
+    }
+}
+
 impl Object {
-    pub fn to_string(&self) -> String {
-        format!(
+    pub fn print(&self) {
+        println!("{}", self)
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "Object->\n {}\nMaterial->\n {}",
             self.object.to_string(),
             self.material.to_string()
         )
     }
-    pub fn print(&self) {
-        println!("{}", self.to_string())
-    }
 }
 
 impl HittableObject for Sphere {
@@ -144,26 +400,26 @@ impl HittableObject for Sphere {
         format!(
             "Sphere with radius {} and center: {}",
             self.radius,
-            self.center.to_string()
+            self.center
         )
     }
 
     fn find_intersection(&self, r: &Ray) -> Float {
-        let a: f64 = 1.0;
-        let b: f64 = (2.0 * (&r.origin.get_x() - &self.center.get_x()) * r.direction.get_x())
-            + (2.0 * (&r.origin.get_y() - &self.center.get_y()) * r.direction.get_y())
-            + (2.0 * (&r.origin.get_z() - &self.center.get_z()) * r.direction.get_z());
-        let c = (&r.origin.get_x() - &self.center.get_x()).powi(2)
-            + (&r.origin.get_y() - &self.center.get_y()).powi(2)
-            + (&r.origin.get_z() - &self.center.get_z()).powi(2)
-            - (&self.radius * &self.radius);
+        let a: f64 = r.direction.dot(&r.direction);
+        let b: f64 = (2.0 * (r.origin.get_x() - self.center.get_x()) * r.direction.get_x())
+            + (2.0 * (r.origin.get_y() - self.center.get_y()) * r.direction.get_y())
+            + (2.0 * (r.origin.get_z() - self.center.get_z()) * r.direction.get_z());
+        let c = (r.origin.get_x() - self.center.get_x()).powi(2)
+            + (r.origin.get_y() - self.center.get_y()).powi(2)
+            + (r.origin.get_z() - self.center.get_z()).powi(2)
+            - self.radius * self.radius;
         let discriminant = b * b - 4.0 * a * c;
         if discriminant > 0.0 {
-            let root_1 = (-1.0 * b - discriminant.sqrt()) / (2.0 * a);
+            let root_1 = (-b - discriminant.sqrt()) / (2.0 * a);
             if root_1 > 0.0 {
                 return root_1;
             }
-            return (-1.0 * b + discriminant.sqrt()) / (2.0 * a);
+            return (-b + discriminant.sqrt()) / (2.0 * a);
         }
         -1.0
     }
@@ -181,9 +437,59 @@ impl HittableObject for Sphere {
         (u, v)
     }
 
+    // u turns phi backwards through 2 pi and v raises theta through pi, the poles have none
+    fn get_uv_derivatives(&self, point: &Vec3) -> Option<(Vec3, Vec3)> {
+        let p = point - &self.center;
+        let rho = (p.x * p.x + p.z * p.z).sqrt();
+        if rho <= 1e-9 * self.radius {
+            return None;
+        }
+        let dpdu = Vec3::new(p.z, 0.0, -p.x) * (2.0 * PI);
+        let dpdv = Vec3::new(-p.y * p.x / rho, rho, -p.y * p.z / rho) * PI;
+        Some((dpdu, dpdv))
+    }
+
+    // Samples the cone of directions subtended by the sphere, or its whole area from inside
+    fn sample(&self, origin: &Vec3, u: Float, v: Float) -> Option<(Vec3, Float)> {
+        let to_center = &self.center - origin;
+        let sin2_theta_max = self.radius * self.radius / to_center.sum_squares();
+        let phi = 2.0 * PI * v;
+        if sin2_theta_max >= 1.0 {
+            let z = 1.0 - 2.0 * u;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            let point = self.center + normal * self.radius;
+            return Some((point, self.pdf(origin, &point)));
+        }
+        // 1 - cos(theta_max) written to keep its precision for small or distant spheres
+        let one_minus_cos_max = sin2_theta_max / (1.0 + (1.0 - sin2_theta_max).sqrt());
+        let cos_theta = 1.0 - u * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let w = to_center.normalize();
+        let (a, b) = w.orthonormal_basis();
+        let direction = a * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + w * cos_theta;
+        let ray = Ray::new(*origin, direction, 0.0);
+        let mut t = self.find_intersection(&ray);
+        if t <= 0.0 {
+            // Grazing directions can miss through rounding, take the closest point instead
+            t = to_center.dot(&direction);
+        }
+        Some((ray.point_at_length(t), 1.0 / (2.0 * PI * one_minus_cos_max)))
+    }
+
+    fn pdf(&self, origin: &Vec3, point: &Vec3) -> Float {
+        let sin2_theta_max = self.radius * self.radius / (&self.center - origin).sum_squares();
+        if sin2_theta_max >= 1.0 {
+            let area = 4.0 * PI * self.radius * self.radius;
+            return area_to_solid_angle(1.0 / area, origin, point, &self.get_normal_at(point));
+        }
+        let one_minus_cos_max = sin2_theta_max / (1.0 + (1.0 - sin2_theta_max).sqrt());
+        1.0 / (2.0 * PI * one_minus_cos_max)
+    }
+
     fn get_bounding_box(&self) -> BoundingBox {
-        let min = &self.center - &Vec3::new(self.radius, self.radius, self.radius);
-        let max = &self.center + &Vec3::new(self.radius, self.radius, self.radius);
+        let min = self.center - Vec3::new(self.radius, self.radius, self.radius);
+        let max = self.center + Vec3::new(self.radius, self.radius, self.radius);
         BoundingBox::new(min, max)
     }
 }
@@ -0,0 +1,219 @@
+// Transformed instances
+use crate::{
+    AnimatedTransform, BoundingBox, Float, HitRecord, HitSamples, Hittable, Light, LightSample,
+    Ray, Transform, Vec3,
+};
+use std::fmt;
+use std::sync::Arc;
+
+// Places a shared hittable in the scene through a transform, so one mesh can appear many
+// times without copying it
+pub struct TransformedInstance {
+    pub object: Arc<dyn Hittable>,
+    pub transform: Transform,
+    bbox: BoundingBox,
+}
+
+impl TransformedInstance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> TransformedInstance {
+        let bbox = transform.transform_bounding_box(&object.bounding_box());
+        TransformedInstance {
+            object,
+            transform,
+            bbox,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for TransformedInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transformed instance\n{}", self.transform)
+    }
+}
+
+impl Hittable for TransformedInstance {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        let local = self.transform.inverse().transform_ray(r);
+        if !self.object.hit(&local, t_min, t_max, rec, samples) {
+            return false;
+        }
+        rec.p = Some(r.point_at_length(rec.t));
+        rec.normal = Some(
+            self.transform
+                .transform_normal(&rec.normal.unwrap())
+                .normalize(),
+        );
+        rec.geometric_normal = rec
+            .geometric_normal
+            .map(|n| self.transform.transform_normal(&n).normalize());
+        rec.dpdu = rec.dpdu.map(|d| self.transform.transform_vector(&d));
+        rec.dpdv = rec.dpdv.map(|d| self.transform.transform_vector(&d));
+        true
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bbox
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.object.primitive_count()
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        // A transform held still at every time
+        let transform = AnimatedTransform::new(self.transform, self.transform, 0.0, 0.0);
+        InstanceLight::wrap(self.object.lights(), &transform)
+    }
+}
+
+// Instance whose transform moves over time, rays see it at their own time
+pub struct AnimatedInstance {
+    pub object: Arc<dyn Hittable>,
+    pub transform: AnimatedTransform,
+    bbox: BoundingBox,
+}
+
+impl AnimatedInstance {
+    pub fn new(object: Arc<dyn Hittable>, transform: AnimatedTransform) -> AnimatedInstance {
+        let bbox = transform.motion_bounds(&object.bounding_box());
+        AnimatedInstance {
+            object,
+            transform,
+            bbox,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for AnimatedInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Animated instance from {} to {}\n{}\n{}",
+            self.transform.time0,
+            self.transform.time1,
+            self.transform.start,
+            self.transform.end
+        )
+    }
+}
+
+impl Hittable for AnimatedInstance {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        let transform = self.transform.interpolate(r.time);
+        let local = transform.inverse().transform_ray(r);
+        if !self.object.hit(&local, t_min, t_max, rec, samples) {
+            return false;
+        }
+        rec.p = Some(r.point_at_length(rec.t));
+        rec.normal = Some(transform.transform_normal(&rec.normal.unwrap()).normalize());
+        rec.geometric_normal = rec
+            .geometric_normal
+            .map(|n| transform.transform_normal(&n).normalize());
+        rec.dpdu = rec.dpdu.map(|d| transform.transform_vector(&d));
+        rec.dpdv = rec.dpdv.map(|d| transform.transform_vector(&d));
+        true
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.bbox
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.object.primitive_count()
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        InstanceLight::wrap(self.object.lights(), &self.transform)
+    }
+}
+
+// Light inside an instance, sampled in the instance's own space. Directions go through the
+// linear part A of the transform, which scales densities per unit solid angle by
+// |A w|^3 / |det A| for a direction w in that space.
+pub struct InstanceLight {
+    pub light: Arc<dyn Light>,
+    pub transform: AnimatedTransform,
+}
+
+impl InstanceLight {
+    fn wrap(lights: Vec<Arc<dyn Light>>, transform: &AnimatedTransform) -> Vec<Arc<dyn Light>> {
+        lights
+            .into_iter()
+            .map(|light| {
+                Arc::new(InstanceLight {
+                    light,
+                    transform: *transform,
+                }) as Arc<dyn Light>
+            })
+            .collect()
+    }
+}
+
+fn determinant(transform: &Transform) -> Float {
+    let m = &transform.matrix.m;
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// Factor turning a density per unit solid angle around the local direction w into one around
+// its image, zero for a degenerate transform
+fn solid_angle_scale(transform: &Transform, w: &Vec3) -> Float {
+    let det = determinant(transform).abs();
+    if det <= 0.0 {
+        return 0.0;
+    }
+    transform.transform_vector(w).magnitude().powi(3) / det
+}
+
+impl Light for InstanceLight {
+    fn sample(&self, origin: &Vec3, time: Float, u: Float, v: Float) -> Option<LightSample> {
+        let transform = self.transform.interpolate(time);
+        let local_origin = transform.inverse().transform_point(origin);
+        let sample = self.light.sample(&local_origin, time, u, v)?;
+        let direction = transform.transform_vector(&sample.direction);
+        let stretch = direction.magnitude();
+        let pdf = sample.pdf * solid_angle_scale(&transform, &sample.direction);
+        if stretch <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: &direction / stretch,
+            distance: sample.distance * stretch,
+            pdf,
+            radiance: sample.radiance,
+        })
+    }
+
+    fn pdf(&self, r: &Ray, t: Float) -> Float {
+        let transform = self.transform.interpolate(r.time);
+        let local = transform.inverse().transform_ray(r);
+        let pdf = self.light.pdf(&local, t);
+        if pdf <= 0.0 {
+            return 0.0;
+        }
+        pdf * solid_angle_scale(&transform, &local.direction.normalize())
+    }
+}
@@ -0,0 +1,88 @@
+// Lights
+use crate::{BaseMaterial, Float, HittableObject, MovingSphere, Object, Ray, Vec3};
+use std::sync::Arc;
+
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: Float,
+    pub pdf: Float,
+    pub radiance: Vec3,
+}
+
+pub trait Light: Send + Sync {
+    // Samples a unit direction from origin towards the light as it is at time, pdf is per unit
+    // solid angle
+    fn sample(&self, origin: &Vec3, time: Float, u: Float, v: Float) -> Option<LightSample>;
+    // Density of sampling the point where r meets the light at t, zero when the light is not there
+    fn pdf(&self, r: &Ray, t: Float) -> Float;
+}
+
+pub fn area_to_solid_angle(pdf_area: Float, origin: &Vec3, point: &Vec3, normal: &Vec3) -> Float {
+    let offset = point - origin;
+    let distance2 = offset.sum_squares();
+    let cosine = normal.dot(&offset).abs() / distance2.sqrt();
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    pdf_area * distance2 / cosine
+}
+
+// Veach's power heuristic with an exponent of 2 for two strategies taking one sample each
+pub fn power_heuristic(pdf: Float, other_pdf: Float) -> Float {
+    let f = pdf * pdf;
+    let g = other_pdf * other_pdf;
+    if f + g <= 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+fn sample_shape(
+    object: &dyn HittableObject,
+    material: &Arc<dyn BaseMaterial>,
+    origin: &Vec3,
+    u: Float,
+    v: Float,
+) -> Option<LightSample> {
+    let (point, pdf) = object.sample(origin, u, v)?;
+    let offset = &point - origin;
+    let distance = offset.magnitude();
+    if pdf <= 0.0 || distance <= 0.0 {
+        return None;
+    }
+    let (pu, pv) = object.get_uv(&point);
+    Some(LightSample {
+        direction: &offset / distance,
+        distance,
+        pdf,
+        radiance: material.emitted(pu, pv, &point),
+    })
+}
+
+fn shape_pdf(object: &dyn HittableObject, r: &Ray, t: Float) -> Float {
+    let distance = object.find_intersection(r);
+    if (distance - t).abs() > 1e-6 * t.max(1.0) {
+        return 0.0;
+    }
+    object.pdf(&r.origin, &r.point_at_length(t))
+}
+
+impl Light for Object {
+    fn sample(&self, origin: &Vec3, _time: Float, u: Float, v: Float) -> Option<LightSample> {
+        sample_shape(self.object.as_ref(), &self.material, origin, u, v)
+    }
+
+    fn pdf(&self, r: &Ray, t: Float) -> Float {
+        shape_pdf(self.object.as_ref(), r, t)
+    }
+}
+
+impl Light for MovingSphere {
+    fn sample(&self, origin: &Vec3, time: Float, u: Float, v: Float) -> Option<LightSample> {
+        sample_shape(&self.sphere_at(time), &self.material, origin, u, v)
+    }
+
+    fn pdf(&self, r: &Ray, t: Float) -> Float {
+        shape_pdf(&self.sphere_at(r.time), r, t)
+    }
+}
@@ -1,31 +1,47 @@
 // Materials
-use crate::{Float, Texture, Vec3, Ray, HitRecord, ConstantTexture};
-use std::rc::Rc;
+use crate::{
+    Bsdf, BuiltinBxdf, ConstantTexture, Float, FuzzyReflection, HitRecord, HitSamples,
+    IsotropicPhase, LambertianReflection, Ray, SpecularDielectric, Texture, Vec3,
+};
+use std::sync::Arc;
 
 pub struct Material {
-    pub albedo: Rc<dyn Texture>,
+    pub albedo: Arc<dyn Texture>,
 }
 
 pub struct DiffuseLight {
-    pub emit: Rc<dyn Texture>,
+    pub emit: Arc<dyn Texture>,
 }
 
 pub struct Metal {
-    pub albedo: Rc<dyn Texture>,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: Float,
 }
 
-pub trait BaseMaterial {
-    fn scatter(
-        &self,
-        rin: &Ray,
-        rec: &HitRecord,
-        attentuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool;
-    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+pub struct Dielectric {
+    pub ior: Float,
+    pub absorption: Option<Vec3>,
+    pub schlick: bool,
+}
+
+// Phase function of a participating medium, scatters equally into every direction
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+pub trait BaseMaterial: Send + Sync {
+    // Scattering at the hit point, None for materials that only emit
+    fn bsdf(&self, rin: &Ray, rec: &HitRecord) -> Option<Bsdf>;
+    fn emitted(&self, _u: Float, _v: Float, _p: &Vec3) -> Vec3 {
         Vec3::zero()
     }
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    // Whether the hit passes through a transparent part, random choices draw from samples
+    fn is_cut_out(&self, _u: Float, _v: Float, _p: &Vec3, _samples: &mut HitSamples) -> bool {
+        false
+    }
     fn print(&self) {
         println!("{}", self.to_string());
     }
@@ -35,7 +51,7 @@ impl DiffuseLight {
     pub fn new(vec: Vec3) -> DiffuseLight {
         let text = ConstantTexture { color: vec };
         DiffuseLight {
-            emit: Rc::new(text),
+            emit: Arc::new(text),
         }
     }
 }
@@ -43,38 +59,77 @@ impl Material {
     pub fn new(vec: Vec3) -> Material {
         let text = ConstantTexture { color: vec };
         Material {
-            albedo: Rc::new(text),
+            albedo: Arc::new(text),
         }
     }
 }
 impl Metal {
-    pub fn new(albedo: Rc<dyn Texture>, fuzz: Float) -> Metal {
+    pub fn new(albedo: Arc<dyn Texture>, fuzz: Float) -> Metal {
         Metal { albedo, fuzz }
     }
 }
 
+impl Isotropic {
+    pub fn new(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Dielectric {
+    pub fn new(ior: Float) -> Dielectric {
+        Dielectric {
+            ior,
+            absorption: None,
+            schlick: false,
+        }
+    }
+
+    // Beer-Lambert absorption coefficients per unit of distance travelled inside the medium
+    pub fn with_absorption(ior: Float, absorption: Vec3) -> Dielectric {
+        Dielectric {
+            ior,
+            absorption: Some(absorption),
+            schlick: false,
+        }
+    }
+
+    pub fn reflectance(&self, cos_theta_i: Float, eta: Float) -> Float {
+        if self.schlick {
+            schlick(cos_theta_i, eta)
+        } else {
+            fresnel_dielectric(cos_theta_i, eta)
+        }
+    }
+}
+
+pub fn schlick(cos_theta_i: Float, eta: Float) -> Float {
+    let r0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos_theta_i).powi(5)
+}
+
+// Unpolarized Fresnel reflectance, eta is the ratio of the incident over the transmitted index
+pub fn fresnel_dielectric(cos_theta_i: Float, eta: Float) -> Float {
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let r_parallel = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    let r_perpendicular = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
 impl BaseMaterial for Material {
     fn to_string(&self) -> String {
         format!("Simple material with color {}", self.albedo.to_string())
     }
 
-    fn scatter(
-        &self,
-        _rin: &Ray,
-        rec: &HitRecord,
-        attentuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        let target = rec.p.unwrap() + rec.normal.unwrap() + Vec3::random_unit();
-        scattered.origin = rec.p.unwrap().clone();
-        scattered.direction = &target - &rec.p.unwrap();
-        let col = self.albedo.as_ref().value(rec.u, rec.v, &rec.p.unwrap());
-        *attentuation = col;
-        true
-    }
-
-    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
-        self.albedo.as_ref().value(u, v, p)
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p.unwrap());
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::LambertianReflection(LambertianReflection { albedo }),
+        ))
     }
 }
 impl BaseMaterial for Metal {
@@ -86,22 +141,15 @@ impl BaseMaterial for Metal {
         )
     }
 
-    fn scatter(
-        &self,
-        rin: &Ray,
-        rec: &HitRecord,
-        _attentuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = rin.direction.reflect(&rec.normal.unwrap());
-        scattered.origin = rec.p.unwrap().clone();
-        scattered.direction = reflected + (&Vec3::random_unit() * self.fuzz);
-        scattered.time = rin.time;
-        *_attentuation = self
-            .albedo
-            .as_ref()
-            .value(rec.u, rec.v, &rec.normal.unwrap());
-        scattered.direction.dot(&rec.normal.unwrap()) > 0.0
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p.unwrap());
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::FuzzyReflection(FuzzyReflection {
+                albedo,
+                fuzz: self.fuzz,
+            }),
+        ))
     }
 }
 impl BaseMaterial for DiffuseLight {
@@ -112,17 +160,61 @@ impl BaseMaterial for DiffuseLight {
         )
     }
 
-    fn scatter(
-        &self,
-        _rin: &Ray,
-        _rec: &HitRecord,
-        _attentuation: &mut Vec3,
-        _scattered: &mut Ray,
-    ) -> bool {
-        false
+    fn bsdf(&self, _rin: &Ray, _rec: &HitRecord) -> Option<Bsdf> {
+        None
     }
 
     fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
         self.emit.value(u, v, p)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+impl BaseMaterial for Dielectric {
+    fn to_string(&self) -> String {
+        format!("Dielectric with index of refraction {}", self.ior)
+    }
+
+    fn bsdf(&self, rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+        // Rays leaving the medium have crossed it since they entered
+        let tint = match (&self.absorption, rec.front_face) {
+            (Some(absorption), false) => {
+                let distance = rec.t * rin.direction.magnitude();
+                Vec3::new(
+                    (-absorption.x * distance).exp(),
+                    (-absorption.y * distance).exp(),
+                    (-absorption.z * distance).exp(),
+                )
+            }
+            _ => Vec3::new(1.0, 1.0, 1.0),
+        };
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::SpecularDielectric(SpecularDielectric {
+                eta,
+                tint,
+                schlick: self.schlick,
+            }),
+        ))
+    }
+}
+impl BaseMaterial for Isotropic {
+    fn to_string(&self) -> String {
+        format!("Isotropic medium with albedo {}", self.albedo.to_string())
+    }
+
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let albedo = self.albedo.value(rec.u, rec.v, &rec.p.unwrap());
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::IsotropicPhase(IsotropicPhase { albedo }),
+        ))
+    }
 }
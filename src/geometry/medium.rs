@@ -0,0 +1,162 @@
+// Participating media
+use crate::{
+    BaseMaterial, BoundingBox, ConstantTexture, Float, HitRecord, HitSamples, Hittable, Isotropic,
+    Ray, Texture, Vec3,
+};
+use std::fmt;
+use std::sync::Arc;
+
+// Volume of constant density filling a closed boundary, rays passing through it scatter
+// with probability 1 - exp(-density * distance) off its phase function
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Hittable>,
+    pub density: Float,
+    pub phase: Arc<dyn BaseMaterial>,
+}
+
+// Homogeneous fog filling a box, or without one everything along every ray, so that rays
+// escaping the scene never reach the environment
+pub struct Fog {
+    pub density: Float,
+    pub phase: Arc<dyn BaseMaterial>,
+    pub bounds: Option<BoundingBox>,
+}
+
+// Records a scattering event at t, media have no surface so the normal faces the ray
+fn medium_record(r: &Ray, t: Float, phase: &Arc<dyn BaseMaterial>) -> HitRecord {
+    let mut rec = HitRecord::new();
+    rec.t = t;
+    rec.p = Some(r.point_at_length(t));
+    rec.normal = Some(r.direction.normalize().inverse());
+    rec.material = Some(phase.clone());
+    rec
+}
+
+// Distance travelled before the next collision in a medium of the given density
+fn sample_free_flight(density: Float, u: Float) -> Float {
+    -(1.0 - u).ln() / density
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: Float,
+        albedo: Arc<dyn Texture>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase: Arc::new(Isotropic::new(albedo)),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for ConstantMedium {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Constant medium with density {} and {}",
+            self.density,
+            self.phase.to_string()
+        )
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        let mut first = HitRecord::new();
+        if !self.boundary.hit(r, t_min, Float::MAX, &mut first, samples) {
+            return false;
+        }
+        // Leaving through a back face means the ray starts inside the volume
+        let (entry, exit) = if first.front_face {
+            let inside = Ray::new(first.p.unwrap(), r.direction, r.time);
+            let mut second = HitRecord::new();
+            if !self
+                .boundary
+                .hit(&inside, 0.001, Float::MAX, &mut second, samples)
+            {
+                return false;
+            }
+            (first.t, first.t + second.t)
+        } else {
+            (t_min, first.t)
+        };
+        let exit = exit.min(t_max);
+        if entry >= exit {
+            return false;
+        }
+        let length = r.direction.magnitude();
+        let distance = sample_free_flight(self.density, samples.get_1d());
+        if distance >= (exit - entry) * length {
+            return false;
+        }
+        *rec = medium_record(r, entry + distance / length, &self.phase);
+        true
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.boundary.bounding_box()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.boundary.primitive_count()
+    }
+}
+
+impl Fog {
+    pub fn new(density: Float, albedo: Vec3, bounds: Option<BoundingBox>) -> Fog {
+        Fog {
+            density,
+            phase: Arc::new(Isotropic::new(Arc::new(ConstantTexture::new(albedo)))),
+            bounds,
+        }
+    }
+
+    // Part of r before t_max inside the fog
+    pub fn interval(&self, r: &Ray, t_max: Float) -> Option<(Float, Float)> {
+        match &self.bounds {
+            Some(bounds) => bounds.ray_interval(r, 0.0, t_max),
+            None => Some((0.0, t_max)),
+        }
+    }
+
+    pub fn transmittance(&self, distance: Float) -> Float {
+        (-self.density * distance).exp()
+    }
+
+    // Scattering event on r between t_min and t_max, None when the ray gets through
+    pub fn sample(&self, r: &Ray, t_min: Float, t_max: Float, u: Float) -> Option<HitRecord> {
+        let length = r.direction.magnitude();
+        let distance = sample_free_flight(self.density, u);
+        if distance >= (t_max - t_min) * length {
+            return None;
+        }
+        Some(medium_record(r, t_min + distance / length, &self.phase))
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Fog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Fog with density {}", self.density)?;
+        if let Some(bounds) = &self.bounds {
+            write!(f, " from {} to {}", bounds.min, bounds.max)?;
+        }
+        write!(f, " and {}", self.phase.to_string())
+    }
+}
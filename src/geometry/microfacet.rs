@@ -0,0 +1,529 @@
+// Microfacet materials
+use crate::{
+    fresnel_dielectric, reflect, BaseMaterial, Bsdf, BsdfFlags, BsdfSample, BuiltinBxdf, Bxdf,
+    Float, HitRecord, Ray, Vec3,
+};
+use std::f64::consts::PI;
+
+// Below this alpha the distribution is too peaked to evaluate, surfaces are treated as smooth
+const SMOOTH_ALPHA: Float = 1e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals around +z. Alpha is the square
+// of the perceptual roughness so that roughness changes the look evenly.
+#[derive(Copy, Clone)]
+pub struct TrowbridgeReitz {
+    pub alpha: Float,
+}
+
+// Metal with a complex index of refraction, eta + i k per color channel
+pub struct Conductor {
+    pub name: String,
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub distribution: TrowbridgeReitz,
+}
+
+// Glass with a rough surface, both reflection and refraction spread over the microfacets
+pub struct RoughDielectric {
+    pub ior: Float,
+    pub distribution: TrowbridgeReitz,
+}
+
+// Reflection off conductor microfacets
+pub struct MicrofacetReflection {
+    pub eta: Vec3,
+    pub k: Vec3,
+    pub distribution: TrowbridgeReitz,
+}
+
+// Reflection and refraction through dielectric microfacets, eta is the ratio of the index on
+// the far side over the one on the side of the normal
+pub struct MicrofacetDielectric {
+    pub eta: Float,
+    pub distribution: TrowbridgeReitz,
+}
+
+// Unpolarized Fresnel reflectance of a conductor for each channel
+pub fn fresnel_conductor(cos_theta_i: Float, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let channel = |eta: Float, k: Float| {
+        let cos2 = cos_theta_i * cos_theta_i;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_theta_i * a;
+        let r_perpendicular = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let r_parallel = r_perpendicular * (t3 - t4) / (t3 + t4);
+        0.5 * (r_parallel + r_perpendicular)
+    };
+    Vec3::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+impl TrowbridgeReitz {
+    pub fn new(roughness: Float) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha: roughness * roughness,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    // Density of microfacet normals per unit projected area
+    pub fn d(&self, wm: &Vec3) -> Float {
+        let cos2 = wm.z * wm.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        let a2 = self.alpha * self.alpha;
+        let e = 1.0 + tan2 / a2;
+        1.0 / (PI * a2 * cos2 * cos2 * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> Float {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return Float::INFINITY;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    // Fraction of the microfacets facing w that are not masked by others
+    pub fn g1(&self, w: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking and shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from w, which is what sample_visible picks
+    pub fn pdf_visible(&self, w: &Vec3, wm: &Vec3) -> Float {
+        if w.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z * self.d(wm) * w.dot(wm).abs()
+    }
+
+    // Heitz, "Sampling the GGX Distribution of Visible Normals", for w above the surface
+    pub fn sample_visible(&self, w: &Vec3, u: (Float, Float)) -> Vec3 {
+        // Stretch the view so the distribution becomes a hemisphere of unit roughness
+        let wh = Vec3::new(self.alpha * w.x, self.alpha * w.y, w.z).normalize();
+        let length2 = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-wh.y, wh.x, 0.0) * (1.0 / length2.sqrt())
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+        // Uniform point on the projected hemisphere, half of the disk is seen at an angle
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let nh = t1 * p1 + t2 * p2 + wh * p3;
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: Float) -> Conductor {
+        Conductor {
+            name: "custom".to_string(),
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    // Measured indices of refraction at wavelengths standing for red, green and blue
+    pub fn from_name(name: &str, roughness: Float) -> Option<Conductor> {
+        let (eta, k) = match name {
+            "gold" => (
+                Vec3::new(0.143, 0.374, 1.442),
+                Vec3::new(3.983, 2.385, 1.603),
+            ),
+            "copper" => (
+                Vec3::new(0.200, 0.924, 1.102),
+                Vec3::new(3.912, 2.452, 2.142),
+            ),
+            "aluminium" | "aluminum" => (
+                Vec3::new(1.657, 0.880, 0.521),
+                Vec3::new(9.224, 6.270, 4.837),
+            ),
+            _ => return None,
+        };
+        let mut conductor = Conductor::new(eta, k, roughness);
+        conductor.name = name.to_string();
+        Some(conductor)
+    }
+}
+
+impl BaseMaterial for Conductor {
+    fn to_string(&self) -> String {
+        format!(
+            "Conductor {} with eta {}, k {} and alpha {}",
+            self.name, self.eta, self.k, self.distribution.alpha
+        )
+    }
+
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::MicrofacetReflection(MicrofacetReflection {
+                eta: self.eta,
+                k: self.k,
+                distribution: self.distribution,
+            }),
+        ))
+    }
+}
+
+impl RoughDielectric {
+    pub fn new(ior: Float, roughness: Float) -> RoughDielectric {
+        RoughDielectric {
+            ior,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+}
+
+impl BaseMaterial for RoughDielectric {
+    fn to_string(&self) -> String {
+        format!(
+            "Rough dielectric with index of refraction {} and alpha {}",
+            self.ior, self.distribution.alpha
+        )
+    }
+
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+        Some(Bsdf::new(
+            rec,
+            BuiltinBxdf::MicrofacetDielectric(MicrofacetDielectric {
+                eta,
+                distribution: self.distribution,
+            }),
+        ))
+    }
+}
+
+impl MicrofacetReflection {
+    fn fresnel(&self, cos_theta_i: Float) -> Vec3 {
+        fresnel_conductor(cos_theta_i.abs().min(1.0), &self.eta, &self.k)
+    }
+}
+
+impl Bxdf for MicrofacetReflection {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 || self.distribution.is_smooth() {
+            return Vec3::zero();
+        }
+        let wm = (wo + wi).normalize();
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        self.fresnel(wo.dot(&wm)) * (d * g / (4.0 * wo.z))
+    }
+
+    fn sample(&self, wo: &Vec3, _uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.distribution.is_smooth() {
+            return Some(BsdfSample {
+                wi: Vec3::new(-wo.x, -wo.y, wo.z),
+                f: self.fresnel(wo.z),
+                pdf: 1.0,
+                flags: BsdfFlags::SPECULAR | BsdfFlags::REFLECTION,
+            });
+        }
+        let wm = self.distribution.sample_visible(wo, u);
+        let wi = reflect(wo, &wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            f: self.eval(wo, &wi),
+            pdf: self.pdf(wo, &wi),
+            wi,
+            flags: BsdfFlags::GLOSSY | BsdfFlags::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        if wo.z <= 0.0 || wi.z <= 0.0 || self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let wm = (wo + wi).normalize();
+        self.distribution.pdf_visible(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        if self.distribution.is_smooth() {
+            BsdfFlags::SPECULAR | BsdfFlags::REFLECTION
+        } else {
+            BsdfFlags::GLOSSY | BsdfFlags::REFLECTION
+        }
+    }
+}
+
+impl MicrofacetDielectric {
+    // Microfacet normal that turns wo into wi, facing the side of the macro normal, or None
+    // when no microfacet visible from both directions could have done it
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+        let wm = if wi.z > 0.0 {
+            wo + wi
+        } else {
+            &(wi * self.eta) + wo
+        };
+        if wm.sum_squares() == 0.0 {
+            return None;
+        }
+        let wm = wm.normalize();
+        let wm = if wm.z < 0.0 { wm.inverse() } else { wm };
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    // BSDF times the cosine and the density of sampling wi
+    fn evaluate(&self, wo: &Vec3, wi: &Vec3) -> (Vec3, Float) {
+        if wo.z <= 0.0 || wi.z == 0.0 || self.distribution.is_smooth() {
+            return (Vec3::zero(), 0.0);
+        }
+        let wm = match self.half_vector(wo, wi) {
+            Some(wm) => wm,
+            None => return (Vec3::zero(), 0.0),
+        };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), 1.0 / self.eta);
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        let pdf_wm = self.distribution.pdf_visible(wo, &wm);
+        if wi.z > 0.0 {
+            let f = d * g * reflectance / (4.0 * wo.z);
+            let pdf = pdf_wm / (4.0 * wo.dot(&wm).abs()) * reflectance;
+            return (Vec3::new(f, f, f), pdf);
+        }
+        // Like Dielectric, radiance is not scaled by eta squared, which cancels out on paths
+        // that enter and leave the glass
+        let denominator = (wi.dot(&wm) + wo.dot(&wm) / self.eta).powi(2);
+        let transmittance = 1.0 - reflectance;
+        let f = d * g * transmittance * (wi.dot(&wm) * wo.dot(&wm)).abs() / (wo.z * denominator);
+        let pdf = pdf_wm * wi.dot(&wm).abs() / denominator * transmittance;
+        (Vec3::new(f, f, f), pdf)
+    }
+}
+
+impl Bxdf for MicrofacetDielectric {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        self.evaluate(wo, wi).0
+    }
+
+    // Picks a microfacet visible from wo, then reflects or refracts off it in proportion to
+    // its Fresnel reflectance
+    fn sample(&self, wo: &Vec3, uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let smooth = self.distribution.is_smooth();
+        let wm = if smooth {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution.sample_visible(wo, u)
+        };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), 1.0 / self.eta);
+        let (wi, probability, flags) = match wo.inverse().refract(&wm, 1.0 / self.eta) {
+            Some(refracted) if uc >= reflectance => {
+                (refracted, 1.0 - reflectance, BsdfFlags::TRANSMISSION)
+            }
+            refracted => {
+                let probability = if refracted.is_some() {
+                    reflectance
+                } else {
+                    1.0
+                };
+                (reflect(wo, &wm), probability, BsdfFlags::REFLECTION)
+            }
+        };
+        // Steep microfacets can send the ray to the wrong side of the surface
+        if wi.z == 0.0 || (wi.z > 0.0) != (flags == BsdfFlags::REFLECTION) {
+            return None;
+        }
+        if smooth {
+            return Some(BsdfSample {
+                wi,
+                f: Vec3::new(probability, probability, probability),
+                pdf: probability,
+                flags: flags | BsdfFlags::SPECULAR,
+            });
+        }
+        let (f, pdf) = self.evaluate(wo, &wi);
+        Some(BsdfSample {
+            wi,
+            f,
+            pdf,
+            flags: flags | BsdfFlags::GLOSSY,
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        self.evaluate(wo, wi).1
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        let lobe = if self.distribution.is_smooth() {
+            BsdfFlags::SPECULAR
+        } else {
+            BsdfFlags::GLOSSY
+        };
+        lobe | BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pcg32;
+
+    const GRID: usize = 600;
+
+    // Midpoints of a GRID x GRID grid over the unit square
+    fn grid() -> impl Iterator<Item = (Float, Float)> {
+        (0..GRID * GRID).map(|i| {
+            (
+                ((i / GRID) as Float + 0.5) / GRID as Float,
+                ((i % GRID) as Float + 0.5) / GRID as Float,
+            )
+        })
+    }
+
+    fn uniform_sphere(u: (Float, Float)) -> Vec3 {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    fn direction(theta: Float) -> Vec3 {
+        let theta = theta.to_radians();
+        Vec3::new(theta.sin(), 0.0, theta.cos())
+    }
+
+    fn assert_near(a: Float, b: Float, tolerance: Float) {
+        assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+    }
+
+    // Checks that pdf is the density sample draws from and that f matches eval, by
+    // comparing integrals over the sphere with their estimates from sampling
+    fn check_sampling(bxdf: &dyn Bxdf, wo: &Vec3) {
+        let count = (GRID * GRID) as Float;
+        let mut pdf_integral = 0.0;
+        let mut f_integral = Vec3::zero();
+        for u in grid() {
+            let wi = uniform_sphere(u);
+            pdf_integral += bxdf.pdf(wo, &wi) * 4.0 * PI / count;
+            f_integral = f_integral + bxdf.eval(wo, &wi) * (4.0 * PI / count);
+        }
+        let mut rng = Pcg32::new(3, 0);
+        let mut valid = 0.0;
+        let mut f_estimate = Vec3::zero();
+        for u in grid() {
+            if let Some(sample) = bxdf.sample(wo, rng.uniform(), u) {
+                assert!(sample.pdf > 0.0);
+                let f = bxdf.eval(wo, &sample.wi);
+                assert!((f - sample.f).magnitude() <= 1e-9 * f.magnitude());
+                assert_near(bxdf.pdf(wo, &sample.wi), sample.pdf, 1e-9 * sample.pdf);
+                valid += 1.0 / count;
+                f_estimate = f_estimate + sample.f * (1.0 / (sample.pdf * count));
+            }
+        }
+        assert_near(pdf_integral, valid, 0.01);
+        assert!(f_integral.x <= 1.0 && f_integral.y <= 1.0 && f_integral.z <= 1.0);
+        assert_near(f_estimate.x, f_integral.x, 0.01);
+        assert_near(f_estimate.y, f_integral.y, 0.01);
+        assert_near(f_estimate.z, f_integral.z, 0.01);
+    }
+
+    #[test]
+    fn distribution_is_normalized() {
+        for roughness in [0.3, 0.5, 0.9].iter() {
+            let distribution = TrowbridgeReitz::new(*roughness);
+            // Projected microfacet area is one, over cos(theta) from 0 to 1
+            let steps = 200_000;
+            let mut area = 0.0;
+            for i in 0..steps {
+                let cos = (i as Float + 0.5) / steps as Float;
+                let wm = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+                area += distribution.d(&wm) * cos * 2.0 * PI / steps as Float;
+            }
+            assert_near(area, 1.0, 1e-3);
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_normalized() {
+        let distribution = TrowbridgeReitz::new(0.6);
+        for theta in [0.0, 45.0, 80.0].iter() {
+            let w = direction(*theta);
+            // Only the microfacets that face w are visible from it
+            let visible: Float = grid()
+                .map(|u| {
+                    let wm = uniform_sphere(u);
+                    if wm.z > 0.0 && wm.dot(&w) > 0.0 {
+                        distribution.pdf_visible(&w, &wm)
+                    } else {
+                        0.0
+                    }
+                })
+                .sum::<Float>()
+                * 4.0
+                * PI
+                / (GRID * GRID) as Float;
+            assert_near(visible, 1.0, 0.01);
+        }
+    }
+
+    #[test]
+    fn conductor_sampling_matches_eval_and_pdf() {
+        let gold = Conductor::from_name("gold", 0.6).unwrap();
+        let bxdf = MicrofacetReflection {
+            eta: gold.eta,
+            k: gold.k,
+            distribution: gold.distribution,
+        };
+        for theta in [10.0, 50.0, 75.0].iter() {
+            check_sampling(&bxdf, &direction(*theta));
+        }
+    }
+
+    #[test]
+    fn dielectric_sampling_matches_eval_and_pdf() {
+        for eta in [1.5, 1.0 / 1.5].iter() {
+            let bxdf = MicrofacetDielectric {
+                eta: *eta,
+                distribution: TrowbridgeReitz::new(0.7),
+            };
+            for theta in [10.0, 50.0, 75.0].iter() {
+                check_sampling(&bxdf, &direction(*theta));
+            }
+        }
+    }
+}
@@ -1,16 +1,44 @@
 // Exports
 mod ray;
 mod material;
+mod bsdf;
+mod quaternion;
 mod hitable;
+mod cutout;
+mod instance;
+mod environment;
+mod light;
+mod medium;
+mod microfacet;
+mod normalmap;
+mod principled;
+mod procedural;
 mod raytracer;
 mod scene;
+mod sky;
 mod texture;
+mod transform;
+mod triangle;
 mod vectors;
 
 pub use ray::*;
 pub use material::*;
+pub use bsdf::*;
+pub use quaternion::*;
 pub use hitable::*;
+pub use cutout::*;
+pub use instance::*;
+pub use environment::*;
+pub use light::*;
+pub use medium::*;
+pub use microfacet::*;
+pub use normalmap::*;
+pub use principled::*;
+pub use procedural::*;
 pub use raytracer::*;
 pub use scene::*;
+pub use sky::*;
 pub use texture::*;
+pub use transform::*;
+pub use triangle::*;
 pub use vectors::*;
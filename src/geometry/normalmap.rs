@@ -0,0 +1,179 @@
+// Normal and bump mapping
+use crate::{BaseMaterial, Bsdf, Float, HitRecord, HitSamples, Ray, Texture, Vec3};
+use std::sync::Arc;
+
+// Step in u and v, or in world units without derivatives, for the height differences
+const BUMP_DELTA: Float = 1e-3;
+
+// Perturbs the shading normal of another material with a tangent space normal map, whose
+// channels in [0, 1] hold the components along the u tangent, the v tangent and the normal
+pub struct NormalMap {
+    pub material: Arc<dyn BaseMaterial>,
+    pub map: Arc<dyn Texture>,
+    // Scales the tangent components, 0 leaves the normal flat
+    pub strength: Float,
+}
+
+// Perturbs the shading normal of another material as if the surface were displaced along it
+// by the average of a texture's channels times scale
+pub struct BumpMap {
+    pub material: Arc<dyn BaseMaterial>,
+    pub height: Arc<dyn Texture>,
+    pub scale: Float,
+}
+
+impl NormalMap {
+    pub fn new(
+        material: Arc<dyn BaseMaterial>,
+        map: Arc<dyn Texture>,
+        strength: Float,
+    ) -> NormalMap {
+        NormalMap {
+            material,
+            map,
+            strength,
+        }
+    }
+}
+
+impl BumpMap {
+    pub fn new(material: Arc<dyn BaseMaterial>, height: Arc<dyn Texture>, scale: Float) -> BumpMap {
+        BumpMap {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn height_at(&self, u: Float, v: Float, p: &Vec3) -> Float {
+        let value = self.height.value(u, v, p);
+        self.scale * (value.x + value.y + value.z) / 3.0
+    }
+}
+
+// Tangents along u and v orthogonal to the normal, with the bitangent on the side of dpdv so
+// that mirrored texture coordinates keep their handedness
+fn tangent_frame(rec: &HitRecord, normal: &Vec3) -> (Vec3, Vec3) {
+    if let (Some(dpdu), Some(dpdv)) = (rec.dpdu, rec.dpdv) {
+        let tangent = dpdu - normal * normal.dot(&dpdu);
+        if tangent.sum_squares() > 1e-12 {
+            let tangent = tangent.normalize();
+            let bitangent = normal.cross(&tangent);
+            if bitangent.dot(&dpdv) < 0.0 {
+                return (tangent, bitangent.inverse());
+            }
+            return (tangent, bitangent);
+        }
+    }
+    normal.orthonormal_basis()
+}
+
+// A perturbed normal facing away from the viewer would leave the surface black, so it is
+// tilted back until the viewer sees it at a grazing angle
+fn face_viewer(normal: &Vec3, rin: &Ray) -> Vec3 {
+    let wo = rin.direction.inverse().normalize();
+    let cos_theta = wo.dot(normal);
+    if cos_theta >= 0.01 {
+        return *normal;
+    }
+    (*normal + wo * (0.01 - cos_theta)).normalize()
+}
+
+fn with_normal(rec: &HitRecord, normal: Vec3) -> HitRecord {
+    let mut shaded = rec.copy();
+    shaded.normal = Some(normal);
+    shaded
+}
+
+impl BaseMaterial for NormalMap {
+    fn to_string(&self) -> String {
+        format!(
+            "Normal map {} with strength {} over {}",
+            self.map.to_string(),
+            self.strength,
+            self.material.to_string()
+        )
+    }
+
+    fn bsdf(&self, rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let normal = rec.normal.unwrap();
+        let (tangent, bitangent) = tangent_frame(rec, &normal);
+        let value = self.map.value(rec.u, rec.v, &rec.p.unwrap());
+        let x = (2.0 * value.x - 1.0) * self.strength;
+        let y = (2.0 * value.y - 1.0) * self.strength;
+        let z = (2.0 * value.z - 1.0).max(0.0);
+        let mapped = tangent * x + bitangent * y + normal * z;
+        if mapped.sum_squares() < 1e-12 {
+            return self.material.bsdf(rin, rec);
+        }
+        let shaded = with_normal(rec, face_viewer(&mapped.normalize(), rin));
+        self.material.bsdf(rin, &shaded)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_cut_out(&self, u: Float, v: Float, p: &Vec3, samples: &mut HitSamples) -> bool {
+        self.material.is_cut_out(u, v, p, samples)
+    }
+}
+
+impl BaseMaterial for BumpMap {
+    fn to_string(&self) -> String {
+        format!(
+            "Bump map {} with scale {} over {}",
+            self.height.to_string(),
+            self.scale,
+            self.material.to_string()
+        )
+    }
+
+    // Differences of the height along u and v tilt the derivatives of the surface point, whose
+    // cross product gives the displaced normal
+    fn bsdf(&self, rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        let normal = rec.normal.unwrap();
+        let p = rec.p.unwrap();
+        let (dpdu, dpdv, du) = match (rec.dpdu, rec.dpdv) {
+            (Some(dpdu), Some(dpdv)) => (dpdu, dpdv, BUMP_DELTA),
+            _ => {
+                let (tangent, bitangent) = normal.orthonormal_basis();
+                (tangent, bitangent, 0.0)
+            }
+        };
+        let dp = BUMP_DELTA;
+        let height = self.height_at(rec.u, rec.v, &p);
+        let height_u = self.height_at(rec.u + du, rec.v, &(p + dpdu * dp));
+        let height_v = self.height_at(rec.u, rec.v + du, &(p + dpdv * dp));
+        let bumped_u = dpdu + normal * ((height_u - height) / dp);
+        let bumped_v = dpdv + normal * ((height_v - height) / dp);
+        let bumped = bumped_u.cross(&bumped_v);
+        if bumped.sum_squares() < 1e-24 {
+            return self.material.bsdf(rin, rec);
+        }
+        let bumped = bumped.normalize();
+        let bumped = if bumped.dot(&normal) < 0.0 {
+            bumped.inverse()
+        } else {
+            bumped
+        };
+        let shaded = with_normal(rec, face_viewer(&bumped, rin));
+        self.material.bsdf(rin, &shaded)
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+        self.material.emitted(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_cut_out(&self, u: Float, v: Float, p: &Vec3, samples: &mut HitSamples) -> bool {
+        self.material.is_cut_out(u, v, p, samples)
+    }
+}
@@ -0,0 +1,290 @@
+// Principled material
+use crate::{
+    clamp, luminance, reflect, sample_cosine_hemisphere, BaseMaterial, Bsdf, BsdfFlags, BsdfSample,
+    BuiltinBxdf, Bxdf, ConstantTexture, Float, HitRecord, MicrofacetDielectric, Ray, Texture,
+    TrowbridgeReitz, Vec3,
+};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Lowest roughness of the lobes, smoother microfacets could no longer be sampled towards lights
+const MIN_ROUGHNESS: Float = 0.032;
+
+// Disney style material mixing a diffuse base with sheen, a specular layer, a clear coat and
+// transmission. Scalar parameters read the average of their texture's channels.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Reflectance of dielectrics at normal incidence, 0.5 gives the 4% of most materials
+    pub specular: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    // Blends the sheen from white towards the hue of the base color
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: Float,
+    pub emission: Option<Arc<dyn Texture>>,
+}
+
+// Lobes of the principled material with its parameters looked up at one hit point
+pub struct PrincipledBxdf {
+    base_color: Vec3,
+    sheen_color: Vec3,
+    specular_f0: Vec3,
+    diffuse_weight: Float,
+    specular_weight: Float,
+    transmission_weight: Float,
+    clearcoat: Float,
+    specular: TrowbridgeReitz,
+    coat: TrowbridgeReitz,
+    glass: MicrofacetDielectric,
+}
+
+fn scalar(texture: &Arc<dyn Texture>, rec: &HitRecord) -> Float {
+    let value = texture.value(rec.u, rec.v, &rec.p.unwrap());
+    (value.x + value.y + value.z) / 3.0
+}
+
+fn schlick_weight(cos_theta: Float) -> Float {
+    clamp(1.0 - cos_theta, 0.0, 1.0).powi(5)
+}
+
+fn schlick_color(f0: &Vec3, cos_theta: Float) -> Vec3 {
+    let weight = schlick_weight(cos_theta);
+    f0 * (1.0 - weight) + Vec3::new(weight, weight, weight)
+}
+
+fn constant(value: Float) -> Arc<dyn Texture> {
+    Arc::new(ConstantTexture::new(Vec3::new(value, value, value)))
+}
+
+fn mix(a: &Vec3, b: &Vec3, t: Float) -> Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+impl Principled {
+    // Rough dielectric defaults, which the public fields can override
+    pub fn new(base_color: Arc<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.1),
+            transmission: constant(0.0),
+            ior: 1.5,
+            emission: None,
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledBxdf {
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p.unwrap());
+        let metallic = clamp(scalar(&self.metallic, rec), 0.0, 1.0);
+        let transmission = clamp(scalar(&self.transmission, rec), 0.0, 1.0);
+        let roughness = clamp(scalar(&self.roughness, rec), MIN_ROUGHNESS, 1.0);
+        let coat_roughness = clamp(scalar(&self.clearcoat_roughness, rec), MIN_ROUGHNESS, 1.0);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let brightness = luminance(&base_color);
+        let tint = if brightness > 0.0 {
+            base_color * (1.0 / brightness)
+        } else {
+            white
+        };
+        let sheen_color =
+            mix(&white, &tint, scalar(&self.sheen_tint, rec)) * scalar(&self.sheen, rec);
+        let dielectric_f0 = 0.08 * scalar(&self.specular, rec);
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+        PrincipledBxdf {
+            base_color,
+            sheen_color,
+            specular_f0: mix(
+                &Vec3::new(dielectric_f0, dielectric_f0, dielectric_f0),
+                &base_color,
+                metallic,
+            ),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            transmission_weight: (1.0 - metallic) * transmission,
+            // Scaled as in the Disney model, where a full coat adds a quarter of its reflection
+            clearcoat: 0.25 * scalar(&self.clearcoat, rec).max(0.0),
+            specular: TrowbridgeReitz::new(roughness),
+            coat: TrowbridgeReitz::new(coat_roughness),
+            glass: MicrofacetDielectric {
+                eta,
+                distribution: TrowbridgeReitz::new(roughness),
+            },
+        }
+    }
+}
+
+impl PrincipledBxdf {
+    // Probabilities of sampling the diffuse, specular, transmission and clear coat lobes
+    fn probabilities(&self, wo: &Vec3) -> [Float; 4] {
+        let mut weights = [
+            self.diffuse_weight * luminance(&self.base_color),
+            self.specular_weight * luminance(&schlick_color(&self.specular_f0, wo.z)),
+            self.transmission_weight,
+            self.clearcoat * (0.04 + 0.96 * schlick_weight(wo.z)),
+        ];
+        let total: Float = weights.iter().sum();
+        if total > 0.0 {
+            for weight in weights.iter_mut() {
+                *weight /= total;
+            }
+        }
+        weights
+    }
+
+    fn eval_lobes(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let mut f = Vec3::zero();
+        if self.transmission_weight > 0.0 {
+            let glass = self.glass.eval(wo, wi);
+            let glass = if wi.z < 0.0 {
+                glass * self.base_color
+            } else {
+                glass
+            };
+            f = f + glass * self.transmission_weight;
+        }
+        if wi.z <= 0.0 {
+            return f;
+        }
+        let wm = (wo + wi).normalize();
+        let cos_d = wi.dot(&wm);
+        if self.diffuse_weight > 0.0 {
+            // Retro-reflection at grazing angles grows with roughness
+            let fd90 = 0.5 + 2.0 * self.specular.alpha.sqrt() * cos_d * cos_d;
+            let fo = 1.0 + (fd90 - 1.0) * schlick_weight(wo.z);
+            let fi = 1.0 + (fd90 - 1.0) * schlick_weight(wi.z);
+            let diffuse = self.base_color * (fo * fi / PI);
+            let sheen = self.sheen_color * schlick_weight(cos_d);
+            f = f + (diffuse + sheen) * (self.diffuse_weight * wi.z);
+        }
+        if self.specular_weight > 0.0 {
+            let d = self.specular.d(&wm);
+            let g = self.specular.g(wo, wi);
+            let fresnel = schlick_color(&self.specular_f0, cos_d);
+            f = f + fresnel * (self.specular_weight * d * g / (4.0 * wo.z));
+        }
+        if self.clearcoat > 0.0 {
+            let d = self.coat.d(&wm);
+            let g = self.coat.g(wo, wi);
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let coat = self.clearcoat * fresnel * d * g / (4.0 * wo.z);
+            f = f + Vec3::new(coat, coat, coat);
+        }
+        f
+    }
+
+    fn pdf_lobes(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        let [diffuse, specular, transmission, clearcoat] = self.probabilities(wo);
+        let mut pdf = 0.0;
+        if transmission > 0.0 {
+            pdf += transmission * self.glass.pdf(wo, wi);
+        }
+        if wi.z <= 0.0 {
+            return pdf;
+        }
+        let wm = (wo + wi).normalize();
+        let reflection = 4.0 * wo.dot(&wm).abs();
+        pdf += diffuse * wi.z / PI;
+        pdf += specular * self.specular.pdf_visible(wo, &wm) / reflection;
+        pdf += clearcoat * self.coat.pdf_visible(wo, &wm) / reflection;
+        pdf
+    }
+
+    fn pick(&self, wo: &Vec3, choice: Float, u: (Float, Float)) -> Option<Vec3> {
+        let [diffuse, specular, transmission, _] = self.probabilities(wo);
+        if choice < diffuse {
+            return Some(sample_cosine_hemisphere(u));
+        }
+        if choice < diffuse + specular {
+            let wm = self.specular.sample_visible(wo, u);
+            return Some(reflect(wo, &wm)).filter(|wi| wi.z > 0.0);
+        }
+        if choice < diffuse + specular + transmission {
+            // The remaining fraction of the choice picks between reflection and refraction
+            let choice = (choice - diffuse - specular) / transmission;
+            return self.glass.sample(wo, choice, u).map(|sample| sample.wi);
+        }
+        let wm = self.coat.sample_visible(wo, u);
+        Some(reflect(wo, &wm)).filter(|wi| wi.z > 0.0)
+    }
+}
+
+impl Bxdf for PrincipledBxdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if wo.z <= 0.0 {
+            return Vec3::zero();
+        }
+        self.eval_lobes(wo, wi)
+    }
+
+    // Samples one lobe and weights by all of them, so lobes overlapping in direction still
+    // give a low variance estimate
+    fn sample(&self, wo: &Vec3, uc: Float, u: (Float, Float)) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wi = self.pick(wo, uc, u)?;
+        let flags = if wi.z > 0.0 {
+            BsdfFlags::REFLECTION
+        } else {
+            BsdfFlags::TRANSMISSION
+        };
+        Some(BsdfSample {
+            f: self.eval_lobes(wo, &wi),
+            pdf: self.pdf_lobes(wo, &wi),
+            wi,
+            flags: flags | BsdfFlags::GLOSSY,
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.pdf_lobes(wo, wi)
+    }
+
+    fn flags(&self) -> BsdfFlags {
+        BsdfFlags::DIFFUSE | BsdfFlags::GLOSSY | BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION
+    }
+}
+
+impl BaseMaterial for Principled {
+    fn to_string(&self) -> String {
+        format!(
+            "Principled material with base color {}, metallic {}, roughness {}, transmission {} and index of refraction {}",
+            self.base_color.to_string(),
+            self.metallic.to_string(),
+            self.roughness.to_string(),
+            self.transmission.to_string(),
+            self.ior
+        )
+    }
+
+    fn bsdf(&self, _rin: &Ray, rec: &HitRecord) -> Option<Bsdf> {
+        Some(Bsdf::new(rec, BuiltinBxdf::Principled(self.lobes(rec))))
+    }
+
+    fn emitted(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+        match &self.emission {
+            Some(emission) => emission.value(u, v, p),
+            None => Vec3::zero(),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emission.is_some()
+    }
+}
@@ -0,0 +1,344 @@
+// Procedural textures
+use crate::{Float, Pcg32, Texture, Vec3};
+use std::fmt;
+use std::sync::Arc;
+
+// Colors at positions in [0, 1], blended linearly in between
+pub struct ColorRamp {
+    pub stops: Vec<(Float, Vec3)>,
+}
+
+// Improved gradient noise of Ken Perlin, with a permutation shuffled from a seed
+pub struct Perlin {
+    permutation: Vec<usize>,
+}
+
+// Alternates between two textures on the cells of a 3D grid with cells of size 1 / scale
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: Float,
+}
+
+// Perlin noise mapped from [-1, 1] onto the ramp
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: Float,
+    pub ramp: ColorRamp,
+}
+
+// Sum of the absolute noise over octaves of doubling frequency
+pub struct TurbulenceTexture {
+    pub noise: Perlin,
+    pub scale: Float,
+    pub octaves: usize,
+    pub ramp: ColorRamp,
+}
+
+// Sine bands along z bent by turbulence
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    pub scale: Float,
+    pub octaves: usize,
+    pub strength: Float,
+    pub ramp: ColorRamp,
+}
+
+// Rings around the y axis, 1 / scale apart, made irregular by noise
+pub struct WoodTexture {
+    pub noise: Perlin,
+    pub scale: Float,
+    pub strength: Float,
+    pub ramp: ColorRamp,
+}
+
+impl ColorRamp {
+    // Stops must be sorted by position
+    pub fn new(stops: Vec<(Float, Vec3)>) -> ColorRamp {
+        ColorRamp { stops }
+    }
+
+    // Colors spread evenly over [0, 1]
+    pub fn even(colors: &[Vec3]) -> ColorRamp {
+        let last = (colors.len().max(2) - 1) as Float;
+        ColorRamp::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as Float / last, *c))
+                .collect(),
+        )
+    }
+
+    pub fn value(&self, t: Float) -> Vec3 {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec3::zero(),
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+            if t <= t1 {
+                let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return c0 * (1.0 - f) + c1 * f;
+            }
+        }
+        last.1
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for ColorRamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stops: Vec<String> = self
+            .stops
+            .iter()
+            .map(|(t, c)| format!("{} at {}", c, t))
+            .collect();
+        write!(f, "Color ramp of {}", stops.join(", "))
+    }
+}
+
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+// Dot product with one of 12 gradients towards the edges of a cube
+fn gradient(hash: usize, x: Float, y: Float, z: Float) -> Float {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = Pcg32::new(seed, 0);
+        let mut permutation: Vec<usize> = (0..256).collect();
+        for i in (1..256).rev() {
+            let j = (rng.next_u32() as usize) % (i + 1);
+            permutation.swap(i, j);
+        }
+        // Doubled so that hashing the corners never needs to wrap
+        permutation.extend_from_within(..);
+        Perlin { permutation }
+    }
+
+    // Smooth noise in about [-1, 1], zero at every integer point
+    pub fn noise(&self, p: &Vec3) -> Float {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+        let xi = (fx as i64 & 255) as usize;
+        let yi = (fy as i64 & 255) as usize;
+        let zi = (fz as i64 & 255) as usize;
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let perm = &self.permutation;
+        let a = perm[xi] + yi;
+        let (aa, ab) = (perm[a] + zi, perm[a + 1] + zi);
+        let b = perm[xi + 1] + yi;
+        let (ba, bb) = (perm[b] + zi, perm[b + 1] + zi);
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(
+                    u,
+                    gradient(perm[aa], x, y, z),
+                    gradient(perm[ba], x - 1.0, y, z),
+                ),
+                lerp(
+                    u,
+                    gradient(perm[ab], x, y - 1.0, z),
+                    gradient(perm[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    gradient(perm[aa + 1], x, y, z - 1.0),
+                    gradient(perm[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    gradient(perm[ab + 1], x, y - 1.0, z - 1.0),
+                    gradient(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    // Fractal sum of absolute noise, each octave at twice the frequency and half the weight,
+    // normalised to stay within [0, 1]
+    pub fn turbulence(&self, p: &Vec3, octaves: usize) -> Float {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut weight = 1.0;
+        let mut point = *p;
+        for _ in 0..octaves.max(1) {
+            sum += weight * self.noise(&point).abs();
+            total += weight;
+            weight *= 0.5;
+            point = &point * 2.0;
+        }
+        (sum / total).min(1.0)
+    }
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: Float) -> CheckerTexture {
+        CheckerTexture { even, odd, scale }
+    }
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: Float, ramp: ColorRamp) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale,
+            ramp,
+        }
+    }
+}
+
+impl TurbulenceTexture {
+    pub fn new(seed: u64, scale: Float, octaves: usize, ramp: ColorRamp) -> TurbulenceTexture {
+        TurbulenceTexture {
+            noise: Perlin::new(seed),
+            scale,
+            octaves,
+            ramp,
+        }
+    }
+}
+
+impl MarbleTexture {
+    pub fn new(
+        seed: u64,
+        scale: Float,
+        octaves: usize,
+        strength: Float,
+        ramp: ColorRamp,
+    ) -> MarbleTexture {
+        MarbleTexture {
+            noise: Perlin::new(seed),
+            scale,
+            octaves,
+            strength,
+            ramp,
+        }
+    }
+}
+
+impl WoodTexture {
+    pub fn new(seed: u64, scale: Float, strength: Float, ramp: ColorRamp) -> WoodTexture {
+        WoodTexture {
+            noise: Perlin::new(seed),
+            scale,
+            strength,
+            ramp,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
+        let cells =
+            (p.x * self.scale).floor() + (p.y * self.scale).floor() + (p.z * self.scale).floor();
+        if (cells as i64).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Checker texture with scale {} between {} and {}",
+            self.scale,
+            self.even.to_string(),
+            self.odd.to_string()
+        )
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vec3) -> Vec3 {
+        let n = self.noise.noise(&(p * self.scale));
+        self.ramp.value(0.5 * (n + 1.0))
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Noise texture with scale {} and {}",
+            self.scale,
+            self.ramp
+        )
+    }
+}
+
+impl Texture for TurbulenceTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vec3) -> Vec3 {
+        self.ramp
+            .value(self.noise.turbulence(&(p * self.scale), self.octaves))
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Turbulence texture with scale {}, {} octaves and {}",
+            self.scale,
+            self.octaves,
+            self.ramp
+        )
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vec3) -> Vec3 {
+        let p = p * self.scale;
+        let phase = p.z + self.strength * self.noise.turbulence(&p, self.octaves);
+        self.ramp.value(0.5 * (1.0 + phase.sin()))
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Marble texture with scale {}, {} octaves, strength {} and {}",
+            self.scale,
+            self.octaves,
+            self.strength,
+            self.ramp
+        )
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vec3) -> Vec3 {
+        let p = p * self.scale;
+        let radius = (p.x * p.x + p.z * p.z).sqrt() + self.strength * self.noise.noise(&p);
+        self.ramp.value(radius - radius.floor())
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Wood texture with scale {}, strength {} and {}",
+            self.scale,
+            self.strength,
+            self.ramp
+        )
+    }
+}
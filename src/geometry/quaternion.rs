@@ -0,0 +1,127 @@
+// Quaternions
+use crate::{Float, Matrix4, Vec3};
+use std::fmt;
+
+#[derive(Copy, Clone)]
+pub struct Quaternion {
+    pub v: Vec3,
+    pub w: Float,
+}
+
+impl Quaternion {
+    pub fn new(v: Vec3, w: Float) -> Quaternion {
+        Quaternion { v, w }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(Vec3::zero(), 1.0)
+    }
+
+    // Reads the rotation held in the upper 3x3 block of a matrix
+    pub fn from_matrix(matrix: &Matrix4) -> Quaternion {
+        let m = &matrix.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt();
+            let f = 0.5 / s;
+            return Quaternion::new(
+                Vec3::new(
+                    (m[2][1] - m[1][2]) * f,
+                    (m[0][2] - m[2][0]) * f,
+                    (m[1][0] - m[0][1]) * f,
+                ),
+                s / 2.0,
+            );
+        }
+        // Start from the largest diagonal element to keep the square root away from zero
+        let mut i = 0;
+        if m[1][1] > m[0][0] {
+            i = 1;
+        }
+        if m[2][2] > m[i][i] {
+            i = 2;
+        }
+        let j = (i + 1) % 3;
+        let k = (j + 1) % 3;
+        let s = (m[i][i] - (m[j][j] + m[k][k]) + 1.0).sqrt();
+        let mut q = [0.0; 3];
+        q[i] = s * 0.5;
+        let f = if s != 0.0 { 0.5 / s } else { 0.0 };
+        q[j] = (m[j][i] + m[i][j]) * f;
+        q[k] = (m[k][i] + m[i][k]) * f;
+        Quaternion::new(Vec3::new(q[0], q[1], q[2]), (m[k][j] - m[j][k]) * f)
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let (x, y, z, w) = (self.v.x, self.v.y, self.v.z, self.w);
+        Matrix4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> Float {
+        self.v.dot(&other.v) + self.w * other.w
+    }
+
+    pub fn scale(&self, factor: Float) -> Quaternion {
+        Quaternion::new(self.v * factor, self.w * factor)
+    }
+
+    pub fn add(&self, other: &Quaternion) -> Quaternion {
+        Quaternion::new(self.v + other.v, self.w + other.w)
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        self.scale(1.0 / self.dot(self).sqrt())
+    }
+
+    // Angle in radians of the rotation taking this orientation to the other one
+    pub fn angle_to(&self, other: &Quaternion) -> Float {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    // Spherical interpolation along the shorter arc
+    pub fn slerp(&self, other: &Quaternion, t: Float) -> Quaternion {
+        let mut end = *other;
+        let mut cos_theta = self.dot(other);
+        if cos_theta < 0.0 {
+            end = other.scale(-1.0);
+            cos_theta = -cos_theta;
+        }
+        if cos_theta > 0.9995 {
+            return self.scale(1.0 - t).add(&end.scale(t)).normalize();
+        }
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        self.scale(((1.0 - t) * theta).sin() / sin_theta)
+            .add(&end.scale((t * theta).sin() / sin_theta))
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Quaternion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Quaternion ({}, {})", self.v, self.w)
+    }
+}
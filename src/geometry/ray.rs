@@ -1,4 +1,6 @@
 use super::vectors::*;
+use crate::Pcg32;
+use std::fmt;
 
 pub struct Ray {
     pub bounces: i32,
@@ -29,26 +31,30 @@ impl Ray {
     pub fn set_time(&mut self, time: Float) {
         self.time = time;
     }
-    pub fn reflected_ray(&self, origin: &Vec3, normal: &Vec3, fuzz: Float) -> Ray {
-        let mut dir: Vec3 = (&(self.direction)).reflect(normal);
+    pub fn reflected_ray(&self, origin: &Vec3, normal: &Vec3, fuzz: Float, rng: &mut Pcg32) -> Ray {
+        let mut dir: Vec3 = (self.direction).reflect(normal);
         if fuzz > 0.0 {
-            dir = &(dir) + &(&Vec3::random_unit() * fuzz);
+            dir = dir + Vec3::random_unit(rng) * fuzz;
         }
-        let mut r = Ray::new(origin.clone(), dir.clone(), 0.0);
+        let mut r = Ray::new(*origin, dir, 0.0);
         r.bounces = self.bounces + 1;
         r
     }
     pub fn point_at_length(&self, length: Float) -> Vec3 {
-        &self.origin + &(&self.direction * length)
+        self.origin + self.direction * length
     }
-    pub fn to_string(&self) -> String {
-        format!(
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Ray {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "Ray\n Start: {}\n Direction: {}",
-            self.origin.to_string(),
-            self.direction.to_string()
+            self.origin,
+            self.direction
         )
     }
-    pub fn print(&self) {
-        println!("{}", self.to_string());
-    }
 }
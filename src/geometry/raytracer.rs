@@ -1,14 +1,13 @@
 // Raytracer
 use super::ray::*;
 use super::vectors::*;
-use crate::geometry::hitable::{HitRecord, Hittable};
-use std::f64::MAX;
-use std::rc::Rc;
+use crate::geometry::hitable::{HitRecord, HitSamples, Hittable};
+use crate::{power_heuristic, Bsdf, Environment, GradientEnvironment, Sampler, Scene};
 
-pub fn get_color_simple(r: &Ray, world: Rc<dyn Hittable>) -> Vec3 {
-    // get_sky_color(r)
+pub fn get_color_simple(r: &Ray, world: &dyn Hittable, sampler: &mut dyn Sampler) -> Vec3 {
     let mut rec = HitRecord::new();
-    if world.hit(r, 0.001, MAX, &mut rec) {
+    let mut samples = HitSamples::new(sampler.get_1d());
+    if world.hit(r, 0.001, Float::MAX, &mut rec, &mut samples) {
         let emitted = rec
             .material
             .clone()
@@ -17,40 +16,116 @@ pub fn get_color_simple(r: &Ray, world: Rc<dyn Hittable>) -> Vec3 {
             .emitted(rec.u, rec.v, &rec.p.unwrap());
         emitted
     } else {
-        return get_sky_color(r);
+        GradientEnvironment::sky().radiance(&r.direction)
     }
 }
 
-pub fn get_color(r: &Ray, world: Rc<dyn Hittable>, depth: i32) -> Vec3 {
+pub fn get_color(
+    r: &Ray,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+    depth: i32,
+    max_depth: i32,
+) -> Vec3 {
+    trace(r, scene, sampler, depth, max_depth, None)
+}
+
+// bsdf_pdf is the density with which the previous vertex sampled r, None when it was
+// specular or r is a camera ray, so that emission found by chance can be weighted
+// against light sampling
+fn trace(
+    r: &Ray,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+    depth: i32,
+    max_depth: i32,
+    bsdf_pdf: Option<Float>,
+) -> Vec3 {
     let mut rec = HitRecord::new();
-    if world.hit(r, 0.001, MAX, &mut rec) {
-        let mut scattered: Ray = Ray::zero();
-        let mut v = Vec3::zero();
-        let emitted = rec
-            .material
-            .clone()
-            .unwrap()
-            .as_ref()
-            .emitted(rec.u, rec.v, &rec.p.unwrap());
-        if depth < 50
-            && rec
-                .material
-                .clone()
-                .unwrap()
-                .as_ref()
-                .scatter(r, &rec, &mut v, &mut scattered)
-        {
-            return emitted + v * get_color(&scattered, world.clone(), depth + 1);
+    let mut samples = HitSamples::new(sampler.get_1d());
+    let mut hit = scene.hit(r, 0.001, Float::MAX, &mut rec, &mut samples);
+    if scene.fog.is_some() {
+        // Distances are sampled in proportion to the transmittance, so surfaces and
+        // the sky behind the fog need no further weighting
+        let t_max = if hit { rec.t } else { Float::MAX };
+        if let Some(event) = scene.sample_fog(r, t_max, sampler.get_1d()) {
+            rec = event;
+            hit = true;
         }
-        return get_sky_color(r);
-    } else {
-        return get_sky_color(r);
     }
+    if !hit {
+        let color = scene.environment.radiance(&r.direction);
+        return match bsdf_pdf {
+            Some(pdf) => color * power_heuristic(pdf, scene.light_pdf(r, Float::INFINITY)),
+            None => color,
+        };
+    }
+    let material = rec.material.clone().unwrap();
+    let mut color = material.emitted(rec.u, rec.v, &rec.p.unwrap());
+    if let Some(pdf) = bsdf_pdf {
+        if material.is_emissive() {
+            color = color * power_heuristic(pdf, scene.light_pdf(r, rec.t));
+        }
+    }
+    if depth >= max_depth {
+        return color;
+    }
+    let bsdf = match material.bsdf(r, &rec) {
+        Some(bsdf) => bsdf,
+        None => return color,
+    };
+    let point = rec.p.unwrap();
+    let wo = r.direction.normalize().inverse();
+    if bsdf.flags().is_non_specular() {
+        color = color + sample_direct(r, scene, sampler, &point, &bsdf, &wo);
+    }
+    let uc = sampler.get_1d();
+    let u = sampler.get_2d();
+    if let Some(sample) = bsdf.sample(&wo, uc, u) {
+        let scattered = Ray::new(point, sample.wi, r.time);
+        let pdf = if sample.flags.is_specular() {
+            None
+        } else {
+            Some(sample.pdf)
+        };
+        let weight = sample.f * (1.0 / sample.pdf);
+        color = color + weight * trace(&scattered, scene, sampler, depth + 1, max_depth, pdf);
+    }
+    color
 }
 
-fn get_sky_color(r: &Ray) -> Vec3 {
-    let unit = r.direction.clone();
-    let t = 0.5 * (unit.get_y() + 1.0);
-    let val = Vec3::new(1.0, 1.0, 1.0) + (&Vec3::new(0.5, 0.7, 1.0) * t);
-    val
+// Light reaching the hit point straight from a sampled light, weighted against BSDF sampling
+fn sample_direct(
+    r: &Ray,
+    scene: &Scene,
+    sampler: &mut dyn Sampler,
+    point: &Vec3,
+    bsdf: &Bsdf,
+    wo: &Vec3,
+) -> Vec3 {
+    let choice = sampler.get_1d();
+    let (u, v) = sampler.get_2d();
+    let mut samples = HitSamples::new(sampler.get_1d());
+    let sample = match scene.sample_light(point, r.time, choice, u, v) {
+        Some(sample) => sample,
+        None => return Vec3::zero(),
+    };
+    let f = bsdf.eval(wo, &sample.direction);
+    if f.x <= 0.0 && f.y <= 0.0 && f.z <= 0.0 {
+        return Vec3::zero();
+    }
+    let shadow = Ray::new(*point, sample.direction, r.time);
+    let mut occluder = HitRecord::new();
+    if scene.hit(
+        &shadow,
+        0.001,
+        sample.distance * (1.0 - 1e-4),
+        &mut occluder,
+        &mut samples,
+    ) {
+        return Vec3::zero();
+    }
+    let transmittance = scene.transmittance(&shadow, sample.distance);
+    let weight = power_heuristic(sample.pdf, bsdf.pdf(wo, &sample.direction));
+    (f * sample.radiance) * (transmittance * weight / sample.pdf)
 }
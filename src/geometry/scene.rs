@@ -1,11 +1,23 @@
 // Scene
-use crate::{BoundingBox, BoundingBoxNode, Camera, Float, HitRecord, Hittable, Ray, Vec3};
-use std::rc::Rc;
+use crate::{
+    BoundingBox, Bvh, Camera, Environment, Float, Fog, GradientEnvironment, HitRecord, HitSamples,
+    Hittable, Light, LightSample, Object, Ray, Vec3,
+};
+use std::sync::Arc;
 
 pub struct Scene {
-    pub objects: Vec<Rc<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
     pub camera: Camera,
-    pub accelerator: Option<Rc<BoundingBoxNode>>,
+    pub accelerator: Option<Arc<Bvh>>,
+    pub lights: Vec<Arc<dyn Light>>,
+    pub fog: Option<Fog>,
+    pub environment: Arc<dyn Environment>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scene {
@@ -18,35 +30,135 @@ impl Scene {
                 Vec3::new(0.0, 1.0, 0.0),
             ),
             accelerator: None,
+            lights: Vec::new(),
+            fog: None,
+            environment: Arc::new(GradientEnvironment::sky()),
         }
     }
 
-    pub fn add_object(&mut self, obj: Rc<dyn Hittable>) {
+    pub fn add_object(&mut self, obj: Arc<dyn Hittable>) {
+        self.lights.extend(obj.lights());
         self.objects.push(obj.clone());
     }
 
+    // Adds a single shape, registering it as a light when its material emits
+    pub fn add_shape(&mut self, shape: Object) {
+        self.add_object(Arc::new(shape));
+    }
+
+    pub fn add_light(&mut self, light: Arc<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    pub fn set_environment(&mut self, environment: Arc<dyn Environment>) {
+        self.environment = environment;
+    }
+
+    // Lights to choose from, a sampled environment counts as one more
+    fn light_count(&self) -> usize {
+        self.lights.len() + self.environment.is_sampled() as usize
+    }
+
+    // Picks one light uniformly, the returned pdf accounts for the choice
+    pub fn sample_light(
+        &self,
+        origin: &Vec3,
+        time: Float,
+        choice: Float,
+        u: Float,
+        v: Float,
+    ) -> Option<LightSample> {
+        let count = self.light_count();
+        if count == 0 {
+            return None;
+        }
+        let index = ((choice * count as Float) as usize).min(count - 1);
+        let mut sample = match self.lights.get(index) {
+            Some(light) => light.sample(origin, time, u, v)?,
+            None => self.environment.sample(u, v)?,
+        };
+        sample.pdf /= count as Float;
+        Some(sample)
+    }
+
+    // Density with which sample_light picks the point where r hits a light at t, an
+    // infinite t stands for a ray that escapes to the environment
+    pub fn light_pdf(&self, r: &Ray, t: Float) -> Float {
+        let count = self.light_count();
+        if count == 0 {
+            return 0.0;
+        }
+        let total: Float = if t.is_finite() {
+            self.lights.iter().map(|l| l.pdf(r, t)).sum()
+        } else if self.environment.is_sampled() {
+            self.environment.pdf(&r.direction)
+        } else {
+            0.0
+        };
+        total / count as Float
+    }
+
+    pub fn set_fog(&mut self, fog: Fog) {
+        self.fog = Some(fog);
+    }
+
+    // Scattering event in the fog before r reaches t_max
+    pub fn sample_fog(&self, r: &Ray, t_max: Float, u: Float) -> Option<HitRecord> {
+        let fog = self.fog.as_ref()?;
+        let (t0, t1) = fog.interval(r, t_max)?;
+        fog.sample(r, t0, t1, u)
+    }
+
+    // Fraction of light that crosses the fog along r up to t_max
+    pub fn transmittance(&self, r: &Ray, t_max: Float) -> Float {
+        let fog = match &self.fog {
+            Some(fog) => fog,
+            None => return 1.0,
+        };
+        match fog.interval(r, t_max) {
+            Some((t0, t1)) => fog.transmittance((t1 - t0) * r.direction.magnitude()),
+            None => 1.0,
+        }
+    }
+
     pub fn get_mutable_camera(&mut self) -> &mut Camera {
         &mut self.camera
     }
-    pub fn get_slice(&self) -> &[Rc<dyn Hittable>] {
+    pub fn get_slice(&self) -> &[Arc<dyn Hittable>] {
         &self.objects[..]
     }
 
-    fn get_mutable_slice(&mut self) -> &mut [Rc<dyn Hittable>] {
-        &mut self.objects[..]
+    pub fn build_accelerator(&mut self) {
+        self.accelerator = Some(Arc::new(Bvh::new(self.get_slice())));
     }
 
-    pub fn build_accelerator(&mut self) {
-        self.accelerator = Some(Rc::new(BoundingBoxNode::new(&mut self.get_mutable_slice())));
+    // Closest hit, through the accelerator once it is built and the object list before
+    pub fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        match &self.accelerator {
+            Some(bvh) => bvh.hit(r, t_min, t_max, rec, samples),
+            None => self.get_slice().hit(r, t_min, t_max, rec, samples),
+        }
     }
-}
-impl Hittable for Scene {
-    fn hit(&self, r: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord) -> bool {
-        // self.get_slice().hit(r, t_min, t_max, rec)
-        self.accelerator.as_ref().unwrap().hit(r, t_min, t_max, rec)
-    }
-    fn bounding_box(&self) -> BoundingBox {
-        // self.get_slice().bounding_box()
-        self.accelerator.as_ref().unwrap().bounding_box()
+
+    // Bounds of all objects, None for an empty scene
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        Some(match &self.accelerator {
+            Some(bvh) => bvh.bounding_box(),
+            None => self.get_slice().bounding_box(),
+        })
+    }
+
+    pub fn primitive_count(&self) -> usize {
+        self.objects.iter().map(|o| o.primitive_count()).sum()
     }
 }
@@ -0,0 +1,262 @@
+// Daylight
+use crate::{clamp, sample_uniform_cone, Environment, Float, LightSample, Vec3};
+use std::f64::consts::PI;
+use std::fmt;
+
+// Luminance in cd/m^2 is scaled so that a white diffuse surface under the midday sun
+// comes out near one
+const LUMINANCE_SCALE: Float = 1.0 / 40_000.0;
+// Luminance of the sun outside the atmosphere in cd/m^2
+const SUN_LUMINANCE: Float = 2.0e9;
+// Seen from the earth, in degrees
+pub const SUN_ANGULAR_RADIUS: Float = 0.2667;
+
+// Clear sky of Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight".
+// Turbidity goes from 2 for a very clear sky to 10 for a hazy one, and the model only
+// holds while the sun is above the horizon.
+pub struct PreethamSky {
+    pub sun_direction: Vec3,
+    pub turbidity: Float,
+    // Perez coefficients A to E of the luminance and the x and y chromaticities
+    perez: [[Float; 5]; 3],
+    // Luminance and chromaticities straight up, divided by their Perez function there
+    zenith: [Float; 3],
+}
+
+// Disc of the sun, dimmed and reddened by the atmosphere it shines through
+pub struct SunDisc {
+    pub direction: Vec3,
+    pub radiance: Vec3,
+    // 1 - cos of the angular radius
+    one_minus_cos_max: Float,
+}
+
+// Preetham sky with the sun in it, where only the sun is sampled as a light
+pub struct SunSky {
+    pub sky: PreethamSky,
+    pub sun: Option<SunDisc>,
+    pub intensity: Float,
+}
+
+// Unit direction of the sun from its elevation above the horizon and its azimuth from +z
+// towards +x, both in degrees
+pub fn sun_direction(elevation: Float, azimuth: Float) -> Vec3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Vec3::new(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        elevation.cos() * azimuth.cos(),
+    )
+}
+
+fn perez_function(coefficients: &[Float; 5], cos_theta: Float, gamma: Float) -> Float {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// CIE xyY to linear sRGB, colors outside the gamut are clipped to zero
+fn xyy_to_rgb(x: Float, y: Float, luminance: Float) -> Vec3 {
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+    )
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: Vec3, turbidity: Float) -> PreethamSky {
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        // Zenith angle of the sun, held at the horizon once it sets
+        let sun_direction = sun_direction.normalize();
+        let theta_s = clamp(sun_direction.y, 0.0, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192) * 1000.0;
+        let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s.powi(3));
+        let x = t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let y = t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+        let mut zenith = [luminance, x, y];
+        for (value, coefficients) in zenith.iter_mut().zip(perez.iter()) {
+            *value /= perez_function(coefficients, 1.0, theta_s);
+        }
+        PreethamSky {
+            sun_direction,
+            turbidity,
+            perez,
+            zenith,
+        }
+    }
+
+    // Radiance of the sky alone, directions below the horizon see the sky at the horizon
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let direction = direction.normalize();
+        let cos_theta = direction.y.max(1e-3);
+        let gamma = clamp(direction.dot(&self.sun_direction), -1.0, 1.0).acos();
+        let mut values = [0.0; 3];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma);
+        }
+        xyy_to_rgb(values[1], values[2], values[0]) * LUMINANCE_SCALE
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for PreethamSky {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Preetham sky with turbidity {} and the sun towards {}",
+            self.turbidity,
+            self.sun_direction
+        )
+    }
+}
+
+impl SunDisc {
+    // Angular radius in degrees, the color follows the Rayleigh and aerosol extinction of the
+    // Preetham model at wavelengths standing for red, green and blue
+    pub fn new(direction: Vec3, angular_radius: Float, turbidity: Float) -> SunDisc {
+        let direction = direction.normalize();
+        let one_minus_cos_max = 2.0 * (angular_radius.to_radians() / 2.0).sin().powi(2);
+        let elevation = direction.y.asin().to_degrees();
+        if elevation <= 0.0 {
+            return SunDisc {
+                direction,
+                radiance: Vec3::zero(),
+                one_minus_cos_max,
+            };
+        }
+        let zenith = 90.0 - elevation;
+        // Relative optical mass of the air the sunlight crosses (Kasten)
+        let mass = 1.0 / (zenith.to_radians().cos() + 0.15 * (93.885 - zenith).powf(-1.253));
+        let beta = 0.04608 * turbidity - 0.04586;
+        let transmittance = |micrometers: Float| {
+            let rayleigh = -0.008735 * micrometers.powf(-4.08) * mass;
+            let aerosol = -beta * micrometers.powf(-1.3) * mass;
+            (rayleigh + aerosol).exp()
+        };
+        let radiance = Vec3::new(
+            transmittance(0.65),
+            transmittance(0.57),
+            transmittance(0.475),
+        ) * (SUN_LUMINANCE * LUMINANCE_SCALE);
+        SunDisc {
+            direction,
+            radiance,
+            one_minus_cos_max,
+        }
+    }
+
+    pub fn contains(&self, direction: &Vec3) -> bool {
+        1.0 - direction.normalize().dot(&self.direction) <= self.one_minus_cos_max
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.radiance.x > 0.0 || self.radiance.y > 0.0 || self.radiance.z > 0.0
+    }
+
+    // Uniform over the disc, the pdf is per unit solid angle
+    pub fn sample(&self, u: Float, v: Float) -> (Vec3, Float) {
+        let local = sample_uniform_cone((u, v), self.one_minus_cos_max);
+        let (a, b) = self.direction.orthonormal_basis();
+        let direction = a * local.x + b * local.y + self.direction * local.z;
+        (direction, 1.0 / (2.0 * PI * self.one_minus_cos_max))
+    }
+
+    pub fn pdf(&self, direction: &Vec3) -> Float {
+        if !self.contains(direction) {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * self.one_minus_cos_max)
+    }
+}
+
+impl SunSky {
+    pub fn new(sun_direction: Vec3, turbidity: Float, intensity: Float) -> SunSky {
+        SunSky {
+            sky: PreethamSky::new(sun_direction, turbidity),
+            sun: Some(SunDisc::new(sun_direction, SUN_ANGULAR_RADIUS, turbidity)),
+            intensity,
+        }
+    }
+}
+
+impl Environment for SunSky {
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let mut radiance = self.sky.radiance(direction);
+        if let Some(sun) = &self.sun {
+            if sun.contains(direction) {
+                radiance = radiance + sun.radiance;
+            }
+        }
+        radiance * self.intensity
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.sun.as_ref().is_some_and(|sun| sun.is_visible())
+    }
+
+    // The sky is smooth enough to be found by BSDF sampling
+    fn sample(&self, u: Float, v: Float) -> Option<LightSample> {
+        let sun = self.sun.as_ref()?;
+        let (direction, pdf) = sun.sample(u, v);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance: Float::INFINITY,
+            pdf,
+            radiance: self.radiance(&direction),
+        })
+    }
+
+    fn pdf(&self, direction: &Vec3) -> Float {
+        self.sun.as_ref().map_or(0.0, |sun| sun.pdf(direction))
+    }
+
+    fn to_string(&self) -> String {
+        let sun = if self.sun.is_some() {
+            ""
+        } else {
+            " without its sun"
+        };
+        format!(
+            "{}{} and intensity {}",
+            self.sky,
+            sun,
+            self.intensity
+        )
+    }
+}
@@ -1,12 +1,17 @@
 // Texture
 use super::vectors::*;
+use crate::{srgb_to_linear, Image};
 
-pub trait Texture {
+pub trait Texture: Send + Sync {
     fn value(&self, u: Float, v: Float, p: &Vec3) -> Vec3;
     fn to_string(&self) -> String;
     fn print(&self) {
         println!("{}", self.to_string());
     }
+    // Whether the values were decoded from sRGB, which data such as normal maps must not be
+    fn is_srgb_decoded(&self) -> bool {
+        false
+    }
 }
 
 pub struct ConstantTexture {
@@ -18,10 +23,145 @@ impl ConstantTexture {
     }
 }
 impl Texture for ConstantTexture {
-    fn value(&self, u: Float, v: Float, p: &Vec3) -> Vec3 {
-        self.color.clone()
+    fn value(&self, _u: Float, _v: Float, _p: &Vec3) -> Vec3 {
+        self.color
+    }
+    fn to_string(&self) -> String {
+        format!("Constant texture with color {}", self.color)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+// How texel coordinates outside the image map back into it
+#[derive(Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+// Image addressed by u to the right and v up, so v = 0 is the bottom row
+pub struct ImageTexture {
+    pub image: Image,
+    pub filter: TextureFilter,
+    pub wrap: WrapMode,
+    pub srgb: bool,
+}
+
+impl TextureFilter {
+    pub fn from_name(name: &str) -> Option<TextureFilter> {
+        match name {
+            "nearest" => Some(TextureFilter::Nearest),
+            "bilinear" => Some(TextureFilter::Bilinear),
+            _ => None,
+        }
+    }
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<WrapMode> {
+        match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "clamp" => Some(WrapMode::Clamp),
+            "mirror" => Some(WrapMode::Mirror),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * size);
+                if m < size {
+                    m
+                } else {
+                    2 * size - 1 - m
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+impl ImageTexture {
+    // Color images are usually stored sRGB encoded and should be decoded to linear values,
+    // data such as roughness or HDR images are used as stored
+    pub fn new(
+        mut image: Image,
+        filter: TextureFilter,
+        wrap: WrapMode,
+        srgb: bool,
+    ) -> ImageTexture {
+        if srgb {
+            for row in image.pixels.iter_mut() {
+                for pixel in row.iter_mut() {
+                    *pixel = Vec3::new(
+                        srgb_to_linear(pixel.x),
+                        srgb_to_linear(pixel.y),
+                        srgb_to_linear(pixel.z),
+                    );
+                }
+            }
+        }
+        ImageTexture {
+            image,
+            filter,
+            wrap,
+            srgb,
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = self.wrap.apply(x, self.image.width);
+        let y = self.wrap.apply(y, self.image.height);
+        self.image.pixels[y][x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _p: &Vec3) -> Vec3 {
+        let x = u * self.image.width as Float;
+        let y = (1.0 - v) * self.image.height as Float;
+        match self.filter {
+            TextureFilter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            TextureFilter::Bilinear => {
+                // Texel centers sit at half integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+                let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
     }
+
     fn to_string(&self) -> String {
-        format!("Constant texture with color {}", self.color.to_string())
+        let filter = match self.filter {
+            TextureFilter::Nearest => "nearest",
+            TextureFilter::Bilinear => "bilinear",
+        };
+        let wrap = match self.wrap {
+            WrapMode::Repeat => "repeat",
+            WrapMode::Clamp => "clamp",
+            WrapMode::Mirror => "mirror",
+        };
+        format!(
+            "Image texture of {}x{} pixels with {} filtering and {} wrapping",
+            self.image.width, self.image.height, filter, wrap
+        )
+    }
+
+    fn is_srgb_decoded(&self) -> bool {
+        self.srgb
     }
 }
@@ -0,0 +1,594 @@
+// Transforms
+use crate::{BoundingBox, Float, Quaternion, Ray, Vec3};
+use std::fmt;
+use std::ops::Mul;
+
+// Row major 4x4 matrix acting on column vectors
+#[derive(Copy, Clone, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[Float; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[Float; 4]; 4]) -> Matrix4 {
+        Matrix4 { m }
+    }
+
+    pub fn identity() -> Matrix4 {
+        Matrix4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4::new(t)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inv = Matrix4::identity().m;
+        for column in 0..4 {
+            let mut pivot = column;
+            for row in column + 1..4 {
+                if a[row][column].abs() > a[pivot][column].abs() {
+                    pivot = row;
+                }
+            }
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inv.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inv[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                if factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inv[row][j] -= factor * inv[column][j];
+                }
+            }
+        }
+        Some(Matrix4::new(inv))
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Matrix4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<String> = self
+            .m
+            .iter()
+            .map(|r| format!(" [{} {} {} {}]", r[0], r[1], r[2], r[3]))
+            .collect();
+        write!(f, "Matrix\n{}", rows.join("\n"))
+    }
+}
+
+impl Mul for &Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, other: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+// An affine transform along with its inverse, so neither is recomputed while tracing
+#[derive(Copy, Clone)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    pub fn from_matrix(matrix: Matrix4) -> Option<Transform> {
+        Some(Transform {
+            inverse: matrix.inverse()?,
+            matrix,
+        })
+    }
+
+    pub fn translate(delta: Vec3) -> Transform {
+        let matrix = Matrix4::new([
+            [1.0, 0.0, 0.0, delta.x],
+            [0.0, 1.0, 0.0, delta.y],
+            [0.0, 0.0, 1.0, delta.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inverse = Matrix4::new([
+            [1.0, 0.0, 0.0, -delta.x],
+            [0.0, 1.0, 0.0, -delta.y],
+            [0.0, 0.0, 1.0, -delta.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform { matrix, inverse }
+    }
+
+    pub fn scale(factors: Vec3) -> Transform {
+        let matrix = Matrix4::new([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inverse = Matrix4::new([
+            [1.0 / factors.x, 0.0, 0.0, 0.0],
+            [0.0, 1.0 / factors.y, 0.0, 0.0],
+            [0.0, 0.0, 1.0 / factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform { matrix, inverse }
+    }
+
+    // Rotations take their angle in degrees, the inverse of a rotation is its transpose
+    pub fn rotate(angle: Float, axis: &Vec3) -> Transform {
+        let a = axis.normalize();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let matrix = Matrix4::new([
+            [
+                a.x * a.x + (1.0 - a.x * a.x) * cos,
+                a.x * a.y * (1.0 - cos) - a.z * sin,
+                a.x * a.z * (1.0 - cos) + a.y * sin,
+                0.0,
+            ],
+            [
+                a.x * a.y * (1.0 - cos) + a.z * sin,
+                a.y * a.y + (1.0 - a.y * a.y) * cos,
+                a.y * a.z * (1.0 - cos) - a.x * sin,
+                0.0,
+            ],
+            [
+                a.x * a.z * (1.0 - cos) - a.y * sin,
+                a.y * a.z * (1.0 - cos) + a.x * sin,
+                a.z * a.z + (1.0 - a.z * a.z) * cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform {
+            inverse: matrix.transpose(),
+            matrix,
+        }
+    }
+
+    pub fn rotate_x(angle: Float) -> Transform {
+        Transform::rotate(angle, &Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    pub fn rotate_y(angle: Float) -> Transform {
+        Transform::rotate(angle, &Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    pub fn rotate_z(angle: Float) -> Transform {
+        Transform::rotate(angle, &Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    // Places an object at position with its z axis pointing at target and its y axis
+    // as close to up as possible. An up parallel to the view is replaced by the world axis
+    // furthest from it, None when position and target coincide.
+    pub fn look_at(position: Vec3, target: Vec3, up: Vec3) -> Option<Transform> {
+        let offset = target - position;
+        if offset.sum_squares() == 0.0 {
+            return None;
+        }
+        let forward = offset.normalize();
+        let mut right = up.cross(&forward);
+        if right.sum_squares() <= 1e-12 * up.sum_squares() {
+            let (x, y, z) = (forward.x.abs(), forward.y.abs(), forward.z.abs());
+            let axis = if x <= y && x <= z {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else if y <= z {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            right = axis.cross(&forward);
+        }
+        let right = right.normalize();
+        let new_up = forward.cross(&right);
+        let matrix = Matrix4::new([
+            [right.x, new_up.x, forward.x, position.x],
+            [right.y, new_up.y, forward.y, position.y],
+            [right.z, new_up.z, forward.z, position.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // The axes are orthonormal, so the inverse rotates back with their transpose
+        let inverse = Matrix4::new([
+            [right.x, right.y, right.z, -right.dot(&position)],
+            [new_up.x, new_up.y, new_up.z, -new_up.dot(&position)],
+            [forward.x, forward.y, forward.z, -forward.dot(&position)],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Some(Transform { matrix, inverse })
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    // Applies this transform first and next after it
+    pub fn then(&self, next: &Transform) -> Transform {
+        next * self
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.matrix.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x / w, y / w, z / w)
+        }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.matrix.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+
+    // Normals go through the inverse transpose to stay perpendicular to their surface
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.inverse.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+
+    // The direction is not renormalized, so distances along the ray match in both spaces
+    pub fn transform_ray(&self, r: &Ray) -> Ray {
+        let mut ray = Ray::new(
+            self.transform_point(&r.origin),
+            self.transform_vector(&r.direction),
+            r.time,
+        );
+        ray.bounces = r.bounces;
+        ray
+    }
+
+    pub fn transform_bounding_box(&self, b: &BoundingBox) -> BoundingBox {
+        let mut result: Option<BoundingBox> = None;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            );
+            let p = self.transform_point(&corner);
+            result = Some(match result {
+                Some(bbox) => bbox.surrounding_point(&p),
+                None => BoundingBox::new(p, p),
+            });
+        }
+        result.unwrap()
+    }
+
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transform\n{}", self.matrix)
+    }
+}
+
+impl Mul for &Transform {
+    type Output = Transform;
+    fn mul(self, other: &Transform) -> Transform {
+        Transform {
+            matrix: &self.matrix * &other.matrix,
+            inverse: &other.inverse * &self.inverse,
+        }
+    }
+}
+
+const MOTION_BOUND_STEPS: usize = 64;
+
+// Splits an affine matrix into translation, rotation and the remaining scale and shear
+// with a polar decomposition, so that each part can be interpolated on its own
+fn decompose(matrix: &Matrix4) -> (Vec3, Quaternion, Matrix4) {
+    let translation = Vec3::new(matrix.m[0][3], matrix.m[1][3], matrix.m[2][3]);
+    let mut linear = *matrix;
+    for i in 0..3 {
+        linear.m[i][3] = 0.0;
+        linear.m[3][i] = 0.0;
+    }
+    linear.m[3][3] = 1.0;
+    let mut rotation = linear;
+    for _ in 0..100 {
+        let inverse_transpose = match rotation.transpose().inverse() {
+            Some(m) => m,
+            None => break,
+        };
+        let mut next = rotation;
+        let mut change: Float = 0.0;
+        for i in 0..3 {
+            for j in 0..3 {
+                next.m[i][j] = 0.5 * (rotation.m[i][j] + inverse_transpose.m[i][j]);
+                change = change.max((next.m[i][j] - rotation.m[i][j]).abs());
+            }
+        }
+        rotation = next;
+        if change < 1e-10 {
+            break;
+        }
+    }
+    // Mirroring matrices leave a rotation with a reflection in it, which a quaternion
+    // cannot hold, so the reflection is moved over to the scale
+    let row = |i: usize| Vec3::new(rotation.m[i][0], rotation.m[i][1], rotation.m[i][2]);
+    if row(0).dot(&row(1).cross(&row(2))) < 0.0 {
+        for i in 0..3 {
+            for j in 0..3 {
+                rotation.m[i][j] = -rotation.m[i][j];
+            }
+        }
+    }
+    let scale = &rotation.transpose() * &linear;
+    (translation, Quaternion::from_matrix(&rotation), scale)
+}
+
+// Transform moving from start at time0 to end at time1, held still outside that interval.
+// Rotations are interpolated along the shortest arc instead of blending matrices.
+#[derive(Copy, Clone)]
+pub struct AnimatedTransform {
+    pub start: Transform,
+    pub end: Transform,
+    pub time0: Float,
+    pub time1: Float,
+    translation: [Vec3; 2],
+    rotation: [Quaternion; 2],
+    scale: [Matrix4; 2],
+}
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform, time0: Float, time1: Float) -> AnimatedTransform {
+        let (t0, r0, s0) = decompose(&start.matrix);
+        let (t1, r1, s1) = decompose(&end.matrix);
+        AnimatedTransform {
+            start,
+            end,
+            time0,
+            time1,
+            translation: [t0, t1],
+            rotation: [r0, r1],
+            scale: [s0, s1],
+        }
+    }
+
+    pub fn interpolate(&self, time: Float) -> Transform {
+        if time <= self.time0 || self.time1 <= self.time0 {
+            return self.start;
+        }
+        if time >= self.time1 {
+            return self.end;
+        }
+        let t = (time - self.time0) / (self.time1 - self.time0);
+        let translation = self.translation[0] * (1.0 - t) + self.translation[1] * t;
+        let rotation = self.rotation[0].slerp(&self.rotation[1], t).to_matrix();
+        let mut scale = Matrix4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                scale.m[i][j] = self.scale[0].m[i][j] * (1.0 - t) + self.scale[1].m[i][j] * t;
+            }
+        }
+        let matrix = &Transform::translate(translation).matrix * &(&rotation * &scale);
+        Transform::from_matrix(matrix).unwrap_or(self.start)
+    }
+
+    // Box holding b at every instant. Poses are sampled densely and the boxes padded by
+    // how far a rotating corner can bulge away from the chord between two samples.
+    pub fn motion_bounds(&self, b: &BoundingBox) -> BoundingBox {
+        let mut bbox = BoundingBox::surrounding_box(
+            &self.start.transform_bounding_box(b),
+            &self.end.transform_bounding_box(b),
+        );
+        let angle = self.rotation[0].angle_to(&self.rotation[1]);
+        if angle == 0.0 && self.scale[0] == self.scale[1] {
+            return bbox;
+        }
+        for step in 1..MOTION_BOUND_STEPS {
+            let time = self.time0
+                + (self.time1 - self.time0) * step as Float / MOTION_BOUND_STEPS as Float;
+            bbox = BoundingBox::surrounding_box(
+                &bbox,
+                &self.interpolate(time).transform_bounding_box(b),
+            );
+        }
+        let mut radius: Float = 0.0;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            );
+            for scale in self.scale.iter() {
+                let scaled = Transform {
+                    matrix: *scale,
+                    inverse: Matrix4::identity(),
+                }
+                .transform_vector(&corner);
+                radius = radius.max(scaled.magnitude());
+            }
+        }
+        let step_angle = angle / MOTION_BOUND_STEPS as Float;
+        let padding = radius * (1.0 - (step_angle / 2.0).cos()) + 1e-6;
+        let pad = Vec3::new(padding, padding, padding);
+        BoundingBox::new(bbox.min - pad, bbox.max + pad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn assert_identity(m: &Matrix4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((m.m[i][j] - expected).abs() < 1e-9, "{}", m);
+            }
+        }
+    }
+
+    fn transforms() -> Vec<Transform> {
+        let translate = Transform::translate(Vec3::new(1.0, -2.0, 3.5));
+        let scale = Transform::scale(Vec3::new(2.0, 0.5, -3.0));
+        let rotate = Transform::rotate(37.0, &Vec3::new(1.0, 2.0, -0.5));
+        vec![
+            translate,
+            scale,
+            rotate,
+            Transform::rotate_x(90.0),
+            scale.then(&rotate).then(&translate),
+            Transform::look_at(
+                Vec3::new(1.0, 2.0, 3.0),
+                Vec3::new(-1.0, 0.0, 2.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            )
+            .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn transforms_carry_their_inverse() {
+        for t in transforms().iter() {
+            assert_identity(&(&t.matrix * &t.inverse));
+            assert_identity(&(&t.inverse * &t.matrix));
+            assert_identity(&(&t.matrix * &t.matrix.inverse().unwrap()));
+            let p = Vec3::new(0.3, -1.2, 2.0);
+            assert_close(t.inverse().transform_point(&t.transform_point(&p)), p);
+        }
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let flat = Transform::scale(Vec3::new(1.0, 0.0, 1.0)).matrix;
+        assert!(flat.inverse().is_none());
+        assert!(Transform::from_matrix(flat).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.5);
+        for t in transforms().iter() {
+            let dot = t
+                .transform_normal(&normal)
+                .dot(&t.transform_vector(&tangent));
+            assert!(dot.abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn look_at_points_z_at_the_target() {
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(4.0, 2.0, -1.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let t = Transform::look_at(position, target, up).unwrap();
+        assert_close(t.transform_point(&Vec3::zero()), position);
+        assert_close(
+            t.transform_vector(&Vec3::new(0.0, 0.0, 1.0)),
+            (target - position).normalize(),
+        );
+        assert_close(t.transform_vector(&Vec3::new(0.0, 1.0, 0.0)), up);
+        assert!(Transform::look_at(position, position, up).is_none());
+    }
+
+    #[test]
+    fn look_at_handles_degenerate_up_vectors() {
+        let position = Vec3::new(0.0, 1.0, 0.0);
+        for (target, up) in [
+            (Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 2.0, 0.0)),
+            (Vec3::new(1.0, 1.0, 1.0), Vec3::zero()),
+        ]
+        .iter()
+        {
+            let t = Transform::look_at(position, *target, *up).unwrap();
+            assert_identity(&(&t.matrix * &t.inverse));
+            let x = t.transform_vector(&Vec3::new(1.0, 0.0, 0.0));
+            let y = t.transform_vector(&Vec3::new(0.0, 1.0, 0.0));
+            let z = t.transform_vector(&Vec3::new(0.0, 0.0, 1.0));
+            assert_close(z, (*target - position).normalize());
+            assert!((x.magnitude() - 1.0).abs() < 1e-9 && (y.magnitude() - 1.0).abs() < 1e-9);
+            assert!(x.dot(&y).abs() < 1e-9 && x.dot(&z).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn animated_transform_keeps_mirroring() {
+        let mirror = Transform::scale(Vec3::new(-1.0, 1.0, 1.0));
+        let end = mirror.then(&Transform::translate(Vec3::new(2.0, 0.0, 0.0)));
+        let animated = AnimatedTransform::new(mirror, end, 0.0, 1.0);
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        for t in [0.01, 0.25, 0.5, 0.99].iter() {
+            let expected = Vec3::new(-1.0 + 2.0 * t, 2.0, 3.0);
+            assert_close(animated.interpolate(*t).transform_point(&p), expected);
+        }
+    }
+
+    #[test]
+    fn animated_transform_rotates_mirrored_shapes() {
+        let mirror = Transform::scale(Vec3::new(1.0, 1.0, -2.0));
+        let start = mirror.then(&Transform::rotate_y(0.0));
+        let end = mirror.then(&Transform::rotate_y(90.0));
+        let animated = AnimatedTransform::new(start, end, 0.0, 2.0);
+        let halfway = mirror.then(&Transform::rotate_y(45.0));
+        let p = Vec3::new(1.0, -2.0, 0.5);
+        assert_close(
+            animated.interpolate(1.0).transform_point(&p),
+            halfway.transform_point(&p),
+        );
+    }
+}
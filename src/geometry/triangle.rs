@@ -0,0 +1,583 @@
+// Triangles and triangle meshes
+use crate::{
+    area_to_solid_angle, BaseMaterial, BoundingBox, Bvh, Float, HitRecord, HitSamples, Hittable,
+    HittableObject, Light, Object, Ray, Vec3,
+};
+use std::fmt;
+use std::sync::Arc;
+
+const EPSILON: Float = 1e-8;
+const BOX_PADDING: Float = 1e-4;
+
+// Moller-Trumbore intersection, returns the distance along the ray or -1.0 on a miss
+pub fn intersect_triangle(r: &Ray, v0: &Vec3, v1: &Vec3, v2: &Vec3) -> Float {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let pvec = r.direction.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < EPSILON {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = &r.origin - v0;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return -1.0;
+    }
+    let qvec = tvec.cross(&edge1);
+    let v = r.direction.dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return -1.0;
+    }
+    edge2.dot(&qvec) * inv_det
+}
+
+// Barycentric weights (w0, w1, w2) of a point lying on the triangle
+pub fn barycentric(p: &Vec3, v0: &Vec3, v1: &Vec3, v2: &Vec3) -> (Float, Float, Float) {
+    let e0 = v1 - v0;
+    let e1 = v2 - v0;
+    let e2 = p - v0;
+    let d00 = e0.dot(&e0);
+    let d01 = e0.dot(&e1);
+    let d11 = e1.dot(&e1);
+    let d20 = e2.dot(&e0);
+    let d21 = e2.dot(&e1);
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() < EPSILON {
+        return (1.0, 0.0, 0.0);
+    }
+    let w1 = (d11 * d20 - d01 * d21) / denom;
+    let w2 = (d00 * d21 - d01 * d20) / denom;
+    (1.0 - w1 - w2, w1, w2)
+}
+
+fn triangle_bounding_box(v0: &Vec3, v1: &Vec3, v2: &Vec3) -> BoundingBox {
+    let padding = Vec3::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+    let min = Vec3::new(
+        v0.x.min(v1.x).min(v2.x),
+        v0.y.min(v1.y).min(v2.y),
+        v0.z.min(v1.z).min(v2.z),
+    );
+    let max = Vec3::new(
+        v0.x.max(v1.x).max(v2.x),
+        v0.y.max(v1.y).max(v2.y),
+        v0.z.max(v1.z).max(v2.z),
+    );
+    BoundingBox::new(min - padding, max + padding)
+}
+
+// Uniformly distributed point over the area of the triangle
+pub fn sample_triangle(v0: &Vec3, v1: &Vec3, v2: &Vec3, u: Float, v: Float) -> Vec3 {
+    let su = u.sqrt();
+    let w1 = v * su;
+    let w2 = 1.0 - su;
+    v0 * (1.0 - w1 - w2) + v1 * w1 + v2 * w2
+}
+
+pub fn triangle_pdf(origin: &Vec3, point: &Vec3, v0: &Vec3, v1: &Vec3, v2: &Vec3) -> Float {
+    let cross = (v1 - v0).cross(&(v2 - v0));
+    let area = cross.magnitude() / 2.0;
+    if area <= 0.0 {
+        return 0.0;
+    }
+    area_to_solid_angle(1.0 / area, origin, point, &cross.normalize())
+}
+
+// Derivatives of the position over the triangle with respect to its texture coordinates
+fn uv_derivatives(vertices: [&Vec3; 3], uv: &[(Float, Float); 3]) -> Option<(Vec3, Vec3)> {
+    let (du1, dv1) = (uv[1].0 - uv[0].0, uv[1].1 - uv[0].1);
+    let (du2, dv2) = (uv[2].0 - uv[0].0, uv[2].1 - uv[0].1);
+    let determinant = du1 * dv2 - dv1 * du2;
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+    let e1 = vertices[1] - vertices[0];
+    let e2 = vertices[2] - vertices[0];
+    let dpdu = &(e1 * dv2 - e2 * dv1) / determinant;
+    let dpdv = &(e2 * du1 - e1 * du2) / determinant;
+    Some((dpdu, dpdv))
+}
+
+fn interpolate_normal(w: (Float, Float, Float), n: &[Vec3; 3]) -> Vec3 {
+    (n[0] * w.0 + (n[1] * w.1 + n[2] * w.2)).normalize()
+}
+
+fn interpolate_uv(w: (Float, Float, Float), uv: &[(Float, Float); 3]) -> (Float, Float) {
+    (
+        uv[0].0 * w.0 + uv[1].0 * w.1 + uv[2].0 * w.2,
+        uv[0].1 * w.0 + uv[1].1 * w.1 + uv[2].1 * w.2,
+    )
+}
+
+#[derive(Copy, Clone)]
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+}
+
+impl Triangle {
+    // Shapes are built already wrapped in an Object with their material
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(mat: Arc<dyn BaseMaterial>, v0: Vec3, v1: Vec3, v2: Vec3) -> Object {
+        let t = Arc::new(Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+        });
+        Object {
+            object: t,
+            material: Arc::clone(&mat),
+        }
+    }
+
+    pub fn with_normals(
+        mat: Arc<dyn BaseMaterial>,
+        vertices: [Vec3; 3],
+        normals: [Vec3; 3],
+    ) -> Object {
+        let t = Arc::new(Triangle {
+            vertices,
+            normals: Some(normals),
+        });
+        Object {
+            object: t,
+            material: Arc::clone(&mat),
+        }
+    }
+}
+
+impl HittableObject for Triangle {
+    fn find_intersection(&self, r: &Ray) -> Float {
+        let [v0, v1, v2] = &self.vertices;
+        intersect_triangle(r, v0, v1, v2)
+    }
+
+    fn get_bounding_box(&self) -> BoundingBox {
+        let [v0, v1, v2] = &self.vertices;
+        triangle_bounding_box(v0, v1, v2)
+    }
+
+    fn get_normal_at(&self, point: &Vec3) -> Vec3 {
+        let [v0, v1, v2] = &self.vertices;
+        match &self.normals {
+            Some(n) => interpolate_normal(barycentric(point, v0, v1, v2), n),
+            None => self.get_geometric_normal_at(point),
+        }
+    }
+
+    fn get_geometric_normal_at(&self, _point: &Vec3) -> Vec3 {
+        let [v0, v1, v2] = &self.vertices;
+        (v1 - v0).cross(&(v2 - v0)).normalize()
+    }
+
+    fn get_uv(&self, point: &Vec3) -> (Float, Float) {
+        let [v0, v1, v2] = &self.vertices;
+        let w = barycentric(point, v0, v1, v2);
+        (w.1, w.2)
+    }
+
+    fn get_uv_derivatives(&self, _point: &Vec3) -> Option<(Vec3, Vec3)> {
+        let [v0, v1, v2] = &self.vertices;
+        Some((v1 - v0, v2 - v0))
+    }
+
+    fn sample(&self, origin: &Vec3, u: Float, v: Float) -> Option<(Vec3, Float)> {
+        let [v0, v1, v2] = &self.vertices;
+        let point = sample_triangle(v0, v1, v2, u, v);
+        Some((point, triangle_pdf(origin, &point, v0, v1, v2)))
+    }
+
+    fn pdf(&self, origin: &Vec3, point: &Vec3) -> Float {
+        let [v0, v1, v2] = &self.vertices;
+        triangle_pdf(origin, point, v0, v1, v2)
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Triangle with vertices {}, {}, {}",
+            self.vertices[0],
+            self.vertices[1],
+            self.vertices[2]
+        )
+    }
+}
+
+// Vertex and index buffers shared by every triangle of a mesh
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(Float, Float)>,
+    pub indices: Vec<usize>,
+}
+
+impl MeshData {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<usize>) -> MeshData {
+        MeshData {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices,
+        }
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn has_normals(&self) -> bool {
+        !self.normals.is_empty()
+    }
+
+    pub fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty()
+    }
+
+    fn face(&self, face: usize) -> [usize; 3] {
+        [
+            self.indices[face * 3],
+            self.indices[face * 3 + 1],
+            self.indices[face * 3 + 2],
+        ]
+    }
+
+    fn face_vertices(&self, face: usize) -> [&Vec3; 3] {
+        let [i0, i1, i2] = self.face(face);
+        [
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        ]
+    }
+}
+
+pub struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl HittableObject for MeshTriangle {
+    fn find_intersection(&self, r: &Ray) -> Float {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        intersect_triangle(r, v0, v1, v2)
+    }
+
+    fn get_bounding_box(&self) -> BoundingBox {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        triangle_bounding_box(v0, v1, v2)
+    }
+
+    fn get_normal_at(&self, point: &Vec3) -> Vec3 {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        if self.mesh.has_normals() {
+            let [i0, i1, i2] = self.mesh.face(self.face);
+            let normals = [
+                self.mesh.normals[i0],
+                self.mesh.normals[i1],
+                self.mesh.normals[i2],
+            ];
+            return interpolate_normal(barycentric(point, v0, v1, v2), &normals);
+        }
+        self.get_geometric_normal_at(point)
+    }
+
+    fn get_geometric_normal_at(&self, _point: &Vec3) -> Vec3 {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        (v1 - v0).cross(&(v2 - v0)).normalize()
+    }
+
+    fn get_uv(&self, point: &Vec3) -> (Float, Float) {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        let w = barycentric(point, v0, v1, v2);
+        if self.mesh.has_uvs() {
+            let [i0, i1, i2] = self.mesh.face(self.face);
+            let uvs = [self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2]];
+            return interpolate_uv(w, &uvs);
+        }
+        (w.1, w.2)
+    }
+
+    fn get_uv_derivatives(&self, _point: &Vec3) -> Option<(Vec3, Vec3)> {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        if !self.mesh.has_uvs() {
+            return Some((v1 - v0, v2 - v0));
+        }
+        let [i0, i1, i2] = self.mesh.face(self.face);
+        let uvs = [self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2]];
+        uv_derivatives([v0, v1, v2], &uvs)
+    }
+
+    fn sample(&self, origin: &Vec3, u: Float, v: Float) -> Option<(Vec3, Float)> {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        let point = sample_triangle(v0, v1, v2, u, v);
+        Some((point, triangle_pdf(origin, &point, v0, v1, v2)))
+    }
+
+    fn pdf(&self, origin: &Vec3, point: &Vec3) -> Float {
+        let [v0, v1, v2] = self.mesh.face_vertices(self.face);
+        triangle_pdf(origin, point, v0, v1, v2)
+    }
+
+    fn to_string(&self) -> String {
+        format!("Mesh triangle {}", self.face)
+    }
+}
+
+pub struct TriangleMesh {
+    pub mesh: Arc<MeshData>,
+    pub material: Arc<dyn BaseMaterial>,
+    pub triangles: Vec<Arc<dyn Hittable>>,
+    accelerator: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        mat: Arc<dyn BaseMaterial>,
+        positions: Vec<Vec3>,
+        indices: Vec<usize>,
+    ) -> Result<TriangleMesh, String> {
+        TriangleMesh::from_data(mat, MeshData::new(positions, indices))
+    }
+
+    // Fails on data that does not describe whole triangles over its own vertices
+    pub fn from_data(mat: Arc<dyn BaseMaterial>, data: MeshData) -> Result<TriangleMesh, String> {
+        if !data.indices.len().is_multiple_of(3) {
+            return Err(format!(
+                "{} indices do not make whole triangles",
+                data.indices.len()
+            ));
+        }
+        if data.face_count() == 0 {
+            return Err("a triangle mesh needs at least one face".to_string());
+        }
+        let vertices = data.positions.len();
+        if data.has_normals() && data.normals.len() != vertices {
+            return Err(format!(
+                "{} normals for {} vertices",
+                data.normals.len(),
+                vertices
+            ));
+        }
+        if data.has_uvs() && data.uvs.len() != vertices {
+            return Err(format!(
+                "{} texture coordinates for {} vertices",
+                data.uvs.len(),
+                vertices
+            ));
+        }
+        if let Some(index) = data.indices.iter().find(|i| **i >= vertices) {
+            return Err(format!(
+                "vertex index {} out of range for {} vertices",
+                index, vertices
+            ));
+        }
+        let mesh = Arc::new(data);
+        let mut triangles: Vec<Arc<dyn Hittable>> = Vec::with_capacity(mesh.face_count());
+        for face in 0..mesh.face_count() {
+            triangles.push(Arc::new(Object {
+                object: Arc::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    face,
+                }),
+                material: mat.clone(),
+            }));
+        }
+        let accelerator = Bvh::new(&triangles[..]);
+        Ok(TriangleMesh {
+            mesh,
+            material: mat,
+            triangles,
+            accelerator,
+        })
+    }
+
+    pub fn print(&self) {
+        println!("{}", self)
+    }
+}
+
+impl fmt::Display for TriangleMesh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Triangle mesh with {} vertices and {} faces\nMaterial->\n {}",
+            self.mesh.positions.len(),
+            self.mesh.face_count(),
+            self.material.to_string()
+        )
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(
+        &self,
+        r: &Ray,
+        t_min: Float,
+        t_max: Float,
+        rec: &mut HitRecord,
+        samples: &mut HitSamples,
+    ) -> bool {
+        self.accelerator.hit(r, t_min, t_max, rec, samples)
+    }
+
+    fn bounding_box(&self) -> BoundingBox {
+        self.accelerator.bounding_box()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.mesh.face_count()
+    }
+
+    fn lights(&self) -> Vec<Arc<dyn Light>> {
+        if !self.material.is_emissive() {
+            return Vec::new();
+        }
+        let mut lights: Vec<Arc<dyn Light>> = Vec::with_capacity(self.mesh.face_count());
+        for face in 0..self.mesh.face_count() {
+            lights.push(Arc::new(Object {
+                object: Arc::new(MeshTriangle {
+                    mesh: self.mesh.clone(),
+                    face,
+                }),
+                material: self.material.clone(),
+            }));
+        }
+        lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConstantTexture, Material};
+
+    fn material() -> Arc<dyn BaseMaterial> {
+        Arc::new(Material {
+            albedo: Arc::new(ConstantTexture::new(Vec3::new(0.5, 0.5, 0.5))),
+        })
+    }
+
+    fn quad() -> MeshData {
+        MeshData::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        )
+    }
+
+    fn mesh_error(data: MeshData) -> String {
+        match TriangleMesh::from_data(material(), data) {
+            Ok(_) => panic!("invalid mesh data was accepted"),
+            Err(e) => e,
+        }
+    }
+
+    fn ray_towards(origin: Vec3, target: Vec3) -> Ray {
+        Ray::new(origin, (target - origin).normalize(), 0.0)
+    }
+
+    #[test]
+    fn intersection_distance_and_barycentrics() {
+        let (v0, v1, v2) = (
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(2.0, 0.0, 2.0),
+            Vec3::new(0.0, 2.0, 2.0),
+        );
+        let r = Ray::new(Vec3::new(0.5, 0.25, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!((intersect_triangle(&r, &v0, &v1, &v2) - 2.0).abs() < 1e-12);
+        let (w0, w1, w2) = barycentric(&Vec3::new(0.5, 0.25, 2.0), &v0, &v1, &v2);
+        assert!((w0 - 0.625).abs() < 1e-12 && (w1 - 0.25).abs() < 1e-12);
+        assert!((w2 - 0.125).abs() < 1e-12);
+        // Triangles are hit from both sides
+        let back = Ray::new(Vec3::new(0.5, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!((intersect_triangle(&back, &v0, &v1, &v2) - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn intersection_misses() {
+        let (v0, v1, v2) = (
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let origin = Vec3::new(0.2, 0.2, -1.0);
+        for target in [
+            Vec3::new(-0.1, 0.5, 0.0),
+            Vec3::new(0.5, -0.1, 0.0),
+            Vec3::new(0.6, 0.6, 0.0),
+        ]
+        .iter()
+        {
+            let r = ray_towards(origin, *target);
+            assert_eq!(intersect_triangle(&r, &v0, &v1, &v2), -1.0);
+        }
+        let parallel = Ray::new(origin, Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(intersect_triangle(&parallel, &v0, &v1, &v2), -1.0);
+        // A triangle behind the ray gives a negative distance
+        let away = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(intersect_triangle(&away, &v0, &v1, &v2) < 0.0);
+    }
+
+    #[test]
+    fn sampled_points_lie_on_the_triangle() {
+        let (v0, v1, v2) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 1.0),
+            Vec3::new(-2.0, 1.0, 4.0),
+        );
+        for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.3, 0.7), (0.9, 0.2)].iter() {
+            let p = sample_triangle(&v0, &v1, &v2, *u, *v);
+            let (w0, w1, w2) = barycentric(&p, &v0, &v1, &v2);
+            assert!(w0 >= -1e-12 && w1 >= -1e-12 && w2 >= -1e-12);
+            let back = v0 * w0 + v1 * w1 + v2 * w2;
+            assert!((back - p).magnitude() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn mesh_hits_with_interpolated_attributes() {
+        let mut data = quad();
+        data.uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = TriangleMesh::from_data(material(), data).unwrap();
+        let r = Ray::new(Vec3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(mesh.hit(&r, 0.001, Float::MAX, &mut rec, &mut HitSamples::new(0.5)));
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+        let miss = Ray::new(Vec3::new(1.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(!mesh.hit(
+            &miss,
+            0.001,
+            Float::MAX,
+            &mut rec,
+            &mut HitSamples::new(0.5)
+        ));
+    }
+
+    #[test]
+    fn mesh_from_valid_data() {
+        let mesh = TriangleMesh::from_data(material(), quad()).unwrap();
+        assert_eq!(mesh.triangles.len(), 2);
+    }
+
+    #[test]
+    fn mesh_rejects_invalid_data() {
+        let mut data = quad();
+        data.indices.push(1);
+        assert!(mesh_error(data).contains("whole triangles"));
+        let mut data = quad();
+        data.indices.clear();
+        assert!(mesh_error(data).contains("at least one face"));
+        let mut data = quad();
+        data.normals = vec![Vec3::new(0.0, 0.0, 1.0); 3];
+        assert!(mesh_error(data).contains("3 normals for 4 vertices"));
+        let mut data = quad();
+        data.uvs = vec![(0.0, 0.0); 5];
+        assert!(mesh_error(data).contains("5 texture coordinates"));
+        let mut data = quad();
+        data.indices[4] = 4;
+        assert!(mesh_error(data).contains("index 4 out of range"));
+    }
+}
@@ -1,8 +1,6 @@
 // Vectors
-// extern crate rand;
-use crate::{map, to_color};
-use rand;
-use std::f64::consts::PI;
+use crate::{map, sample_unit_sphere, to_color, Pcg32};
+use std::fmt;
 use std::ops::Index;
 use std::ops::{Add, Div, Mul, Sub};
 pub type Float = f64;
@@ -25,11 +23,7 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
     pub fn print(&self) {
-        println!("{}", self.to_string());
-    }
-
-    pub fn to_string(&self) -> String {
-        format!("Vector (x: {}, y: {}, z: {})", self.x, self.y, self.z)
+        println!("{}", self);
     }
 
     pub fn to_pixel_string(&self, a: Float, b: Float) -> String {
@@ -127,6 +121,17 @@ impl Vec3 {
         (&(normal * (normal.dot(self) * 2.0f64)) - self).inverse()
     }
 
+    pub fn refract(&self, normal: &Vec3, eta_ratio: Float) -> Option<Vec3> {
+        let unit = self.normalize();
+        let cos_theta = -unit.dot(normal);
+        let sin2_theta_t = eta_ratio * eta_ratio * (1.0 - cos_theta * cos_theta);
+        if sin2_theta_t > 1.0 {
+            return None;
+        }
+        let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+        Some(unit * eta_ratio + normal * (eta_ratio * cos_theta - cos_theta_t))
+    }
+
     pub fn rotate_x(&self, angle: Float) -> Vec3 {
         Vec3 {
             x: self.x,
@@ -139,7 +144,7 @@ impl Vec3 {
         Vec3 {
             x: self.x * angle.cos() + self.z * angle.sin(),
             y: self.y,
-            z: self.x * -1.0 * angle.sin() + self.z * angle.cos(),
+            z: -self.x * angle.sin() + self.z * angle.cos(),
         }
     }
 
@@ -151,25 +156,29 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit() -> Vec3 {
-        let mut v = Vec3 {
-            x: 1.0,
-            y: 0.0,
-            z: 0.0,
-        };
-        v = v.rotate_x(rand::random::<Float>() * PI * 2.0);
-        v = v.rotate_y(rand::random::<Float>() * PI * 2.0);
-        v = v.rotate_z(rand::random::<Float>() * PI * 2.0);
-        v
+    // Uniformly distributed over the unit sphere
+    pub fn random_unit(rng: &mut Pcg32) -> Vec3 {
+        sample_unit_sphere((rng.uniform(), rng.uniform()))
+    }
+
+    // Any two unit vectors completing an orthonormal basis with this unit vector
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        let sign = 1.0f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
     }
 
-    pub fn random_in_disk() -> Vec3 {
+    pub fn random_in_disk(rng: &mut Pcg32) -> Vec3 {
         let mut v: Vec3;
         loop {
-            let x = rand::random::<Float>();
-            let y = rand::random::<Float>();
+            let x = rng.uniform();
+            let y = rng.uniform();
             let z = 0.0;
-            v = &(&Vec3::new(x, y, z) * 2.0) - &Vec3::new(1.0, 1.0, 0.0);
+            v = Vec3::new(x, y, z) * 2.0 - Vec3::new(1.0, 1.0, 0.0);
             if v.dot(&v) <= 1.0 {
                 break;
             }
@@ -177,14 +186,6 @@ impl Vec3 {
         v
     }
 
-    pub fn clone(&self) -> Vec3 {
-        Vec3 {
-            x: self.x,
-            y: self.y,
-            z: self.z,
-        }
-    }
-
     pub fn normalize(&self) -> Vec3 {
         self.clone_direction(1.0)
     }
@@ -194,7 +195,7 @@ impl Vec3 {
     }
 
     pub fn clone_direction(&self, value: Float) -> Vec3 {
-        return self * (value / self.magnitude());
+        self * (value / self.magnitude())
     }
     pub fn squash(&self, maximum: Float) -> Vec3 {
         Vec3 {
@@ -205,6 +206,12 @@ impl Vec3 {
     }
 }
 
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Vector (x: {}, y: {}, z: {})", self.x, self.y, self.z)
+    }
+}
+
 impl Add for &Vec3 {
     type Output = Vec3;
     fn add(self, other: &Vec3) -> Vec3 {
@@ -0,0 +1,463 @@
+// HDR image writers
+use crate::{Image, ImageWriter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct PfmWriter {}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ExrCompression {
+    None,
+    Zip,
+}
+
+pub struct ExrWriter {
+    pub pixel_type: ExrPixelType,
+    pub compression: ExrCompression,
+}
+
+impl Default for PfmWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PfmWriter {
+    pub fn new() -> PfmWriter {
+        PfmWriter {}
+    }
+}
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, image: &Image, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // A negative scale marks little endian data
+        write!(file, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+        // Scanlines are stored from the bottom of the image to the top
+        for row in image.pixels.iter().rev() {
+            for pixel in row.iter() {
+                file.write_all(&(pixel.x as f32).to_le_bytes())?;
+                file.write_all(&(pixel.y as f32).to_le_bytes())?;
+                file.write_all(&(pixel.z as f32).to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+}
+
+// Rounds to the nearest half precision value, overflowing to infinity
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let mut half = (mantissa >> shift) as u16;
+        let round_bit = 1 << (shift - 1);
+        if mantissa & round_bit != 0 && mantissa & (3 * round_bit - 1) != 0 {
+            half += 1;
+        }
+        return sign | half;
+    }
+    let mut half = ((half_exponent as u16) << 10) | (mantissa >> 13) as u16;
+    if mantissa & 0x1000 != 0 && mantissa & 0x2fff != 0 {
+        // Carries into the exponent when the mantissa overflows, which is still correct
+        half += 1;
+    }
+    sign | half
+}
+
+impl ExrCompression {
+    fn id(&self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(&self) -> usize {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => 16,
+        }
+    }
+}
+
+impl ExrPixelType {
+    fn id(&self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Byte interleaving and delta predictor applied before deflating, as the ZIP codec expects
+fn zip_block(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut reordered = vec![0u8; raw.len()];
+    for (i, byte) in raw.iter().enumerate() {
+        if i % 2 == 0 {
+            reordered[i / 2] = *byte;
+        } else {
+            reordered[half + i / 2] = *byte;
+        }
+    }
+    let mut previous = reordered[0];
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    miniz_oxide::deflate::compress_to_vec_zlib(&reordered, 6)
+}
+
+impl ExrWriter {
+    pub fn new(pixel_type: ExrPixelType, compression: ExrCompression) -> ExrWriter {
+        ExrWriter {
+            pixel_type,
+            compression,
+        }
+    }
+
+    fn header(&self, image: &Image) -> Vec<u8> {
+        let mut header: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+        let mut channels: Vec<u8> = Vec::new();
+        // Channels are listed in alphabetical order
+        for name in ["B", "G", "R"].iter() {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&self.pixel_type.id().to_le_bytes());
+            channels.extend_from_slice(&[0, 0, 0, 0]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        write_attribute(&mut header, "channels", "chlist", &channels);
+        write_attribute(
+            &mut header,
+            "compression",
+            "compression",
+            &[self.compression.id()],
+        );
+        let mut window: Vec<u8> = Vec::new();
+        for v in [0, 0, image.width as i32 - 1, image.height as i32 - 1].iter() {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        let mut center: Vec<u8> = Vec::new();
+        center.extend_from_slice(&0f32.to_le_bytes());
+        center.extend_from_slice(&0f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &center);
+        write_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        header.push(0);
+        header
+    }
+
+    fn push_value(&self, data: &mut Vec<u8>, value: f64) {
+        match self.pixel_type {
+            ExrPixelType::Half => data.extend_from_slice(&f32_to_half(value as f32).to_le_bytes()),
+            ExrPixelType::Float => data.extend_from_slice(&(value as f32).to_le_bytes()),
+        }
+    }
+
+    fn block(&self, image: &Image, first_line: usize) -> Vec<u8> {
+        let last_line = image
+            .height
+            .min(first_line + self.compression.lines_per_block());
+        let mut raw: Vec<u8> = Vec::new();
+        for row in image.pixels[first_line..last_line].iter() {
+            for pixel in row.iter() {
+                self.push_value(&mut raw, pixel.z);
+            }
+            for pixel in row.iter() {
+                self.push_value(&mut raw, pixel.y);
+            }
+            for pixel in row.iter() {
+                self.push_value(&mut raw, pixel.x);
+            }
+        }
+        if self.compression == ExrCompression::Zip {
+            let compressed = zip_block(&raw);
+            // Blocks that do not shrink are stored as they are
+            if compressed.len() < raw.len() {
+                return compressed;
+            }
+        }
+        raw
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, image: &Image, path: &Path) -> io::Result<()> {
+        let header = self.header(image);
+        let lines = self.compression.lines_per_block();
+        let blocks: Vec<Vec<u8>> = (0..image.height)
+            .step_by(lines)
+            .map(|y| self.block(image, y))
+            .collect();
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header)?;
+        let mut offset = (header.len() + blocks.len() * 8) as u64;
+        for block in blocks.iter() {
+            file.write_all(&offset.to_le_bytes())?;
+            offset += 8 + block.len() as u64;
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            file.write_all(&((i * lines) as i32).to_le_bytes())?;
+            file.write_all(&(block.len() as i32).to_le_bytes())?;
+            file.write_all(block)?;
+        }
+        file.flush()
+    }
+}
+
+impl Image {
+    pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        PfmWriter::new().write(self, path.as_ref())
+    }
+
+    pub fn write_exr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        ExrWriter::new(ExrPixelType::Half, ExrCompression::Zip).write(self, path.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_pfm, Vec3};
+    use std::path::PathBuf;
+
+    fn half_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((half >> 10) & 0x1f) as i32;
+        let mantissa = (half & 0x3ff) as f32;
+        match exponent {
+            0 => sign * mantissa * 2f32.powi(-24),
+            0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1f => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustracer-{}-{}", std::process::id(), name))
+    }
+
+    fn test_image() -> Image {
+        let mut image = Image::new(5, 19);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let color = Vec3::new(x as f64 * 0.25, y as f64 * 0.5, (x * y) as f64 - 3.0);
+                image.put_pixel(color, y, x);
+            }
+        }
+        image
+    }
+
+    // Minimal scanline EXR decoder for the subset ExrWriter produces
+    fn read_exr(data: &[u8], writer: &ExrWriter, width: usize, height: usize) -> Image {
+        let (pixel_type, compression) = (writer.pixel_type, writer.compression);
+        let mut offset = 8;
+        while data[offset] != 0 {
+            let name_end = offset + data[offset..].iter().position(|b| *b == 0).unwrap();
+            let kind_end =
+                name_end + 1 + data[name_end + 1..].iter().position(|b| *b == 0).unwrap();
+            let mut size = [0u8; 4];
+            size.copy_from_slice(&data[kind_end + 1..kind_end + 5]);
+            offset = kind_end + 5 + i32::from_le_bytes(size) as usize;
+        }
+        let table = offset + 1;
+        let size = if pixel_type == ExrPixelType::Half {
+            2
+        } else {
+            4
+        };
+        let mut image = Image::new(width, height);
+        let mut y = 0;
+        let mut block_index = 0;
+        while y < height {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[table + block_index * 8..table + block_index * 8 + 8]);
+            let start = u64::from_le_bytes(bytes) as usize;
+            let mut word = [0u8; 4];
+            word.copy_from_slice(&data[start..start + 4]);
+            assert_eq!(i32::from_le_bytes(word) as usize, y);
+            word.copy_from_slice(&data[start + 4..start + 8]);
+            let length = i32::from_le_bytes(word) as usize;
+            let block = &data[start + 8..start + 8 + length];
+            let lines = (height - y).min(compression.lines_per_block());
+            let raw_length = lines * width * 3 * size;
+            let raw = if length < raw_length {
+                let mut predicted = miniz_oxide::inflate::decompress_to_vec_zlib(block).unwrap();
+                for i in 1..predicted.len() {
+                    predicted[i] = predicted[i]
+                        .wrapping_add(predicted[i - 1])
+                        .wrapping_sub(128);
+                }
+                let half = predicted.len().div_ceil(2);
+                (0..predicted.len())
+                    .map(|i| {
+                        if i % 2 == 0 {
+                            predicted[i / 2]
+                        } else {
+                            predicted[half + i / 2]
+                        }
+                    })
+                    .collect()
+            } else {
+                block.to_vec()
+            };
+            assert_eq!(raw.len(), raw_length);
+            let values: Vec<f64> = raw
+                .chunks(size)
+                .map(|b| match pixel_type {
+                    ExrPixelType::Half => half_to_f32(u16::from_le_bytes([b[0], b[1]])) as f64,
+                    ExrPixelType::Float => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                })
+                .collect();
+            for (line, row) in values.chunks(width * 3).enumerate() {
+                for x in 0..width {
+                    let color = Vec3::new(row[2 * width + x], row[width + x], row[x]);
+                    image.put_pixel(color, y + line, x);
+                }
+            }
+            y += lines;
+            block_index += 1;
+        }
+        image
+    }
+
+    fn assert_images_equal(a: &Image, b: &Image) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        for y in 0..a.height {
+            for x in 0..a.width {
+                let (p, q) = (a.pixels[y][x], b.pixels[y][x]);
+                assert_eq!((p.x, p.y, p.z), (q.x, q.y, q.z), "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn half_round_trips_every_finite_value() {
+        for half in 0..=0xffffu16 {
+            if half & 0x7c00 == 0x7c00 {
+                continue;
+            }
+            assert_eq!(f32_to_half(half_to_f32(half)), half, "half {:#06x}", half);
+        }
+    }
+
+    #[test]
+    fn half_denormals() {
+        let smallest = 2f32.powi(-24);
+        assert_eq!(f32_to_half(smallest), 0x0001);
+        assert_eq!(f32_to_half(-smallest), 0x8001);
+        assert_eq!(f32_to_half(1023.0 * smallest), 0x03ff);
+        assert_eq!(f32_to_half(2f32.powi(-14)), 0x0400);
+        // Half of the smallest denormal ties to even zero, anything above rounds up
+        assert_eq!(f32_to_half(smallest * 0.5), 0x0000);
+        assert_eq!(f32_to_half(smallest * 0.51), 0x0001);
+        assert_eq!(f32_to_half(smallest * 1.5), 0x0002);
+        assert_eq!(f32_to_half(2f32.powi(-30)), 0x0000);
+        assert_eq!(f32_to_half(-2f32.powi(-30)), 0x8000);
+        // Rounding up from the largest denormal reaches the smallest normal
+        assert_eq!(f32_to_half(1023.75 * smallest), 0x0400);
+    }
+
+    #[test]
+    fn half_rounds_to_nearest_even() {
+        let ulp = 2f32.powi(-10);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + ulp * 0.5), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + ulp * 0.51), 0x3c01);
+        assert_eq!(f32_to_half(1.0 + ulp * 1.5), 0x3c02);
+        assert_eq!(f32_to_half(1.0 + ulp * 1.49), 0x3c01);
+        // A mantissa carry moves to the next exponent
+        assert_eq!(f32_to_half(2.0 - ulp * 0.25), 0x4000);
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+    }
+
+    #[test]
+    fn half_overflow_infinity_and_nan() {
+        assert_eq!(f32_to_half(65519.0), 0x7bff);
+        assert_eq!(f32_to_half(65520.0), 0x7c00);
+        assert_eq!(f32_to_half(1e10), 0x7c00);
+        assert_eq!(f32_to_half(-1e10), 0xfc00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NEG_INFINITY), 0xfc00);
+        let nan = f32_to_half(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x03ff, 0);
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let image = test_image();
+        let path = temp_path("round-trip.pfm");
+        image.write_pfm(&path).unwrap();
+        let loaded = load_pfm(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_images_equal(&image, &loaded);
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let image = test_image();
+        for pixel_type in [ExrPixelType::Half, ExrPixelType::Float].iter() {
+            for compression in [ExrCompression::None, ExrCompression::Zip].iter() {
+                let path = temp_path("round-trip.exr");
+                let writer = ExrWriter::new(*pixel_type, *compression);
+                writer.write(&image, &path).unwrap();
+                let data = std::fs::read(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                let loaded = read_exr(&data, &writer, image.width, image.height);
+                // The test values are all exact in half precision
+                assert_images_equal(&image, &loaded);
+            }
+        }
+    }
+}
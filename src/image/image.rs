@@ -1,8 +1,10 @@
 // Image
-use crate::{Float, Vec3};
-use std::string::String;
+use crate::{Exposure, Float, TileData, Vec3};
+use std::fmt;
 pub struct Image {
     pub pixels: Vec<Vec<Vec3>>,
+    // Opacity of every pixel, for images loaded from files that store it
+    pub alpha: Option<Vec<Vec<Float>>>,
     pub width: usize,
     pub height: usize,
 }
@@ -39,16 +41,31 @@ impl ImageBuilder {
     }
 
     pub fn is_finished(&self) -> bool {
-        self.finished >= self.max_passes
+        self.finished >= self.width * self.height
     }
 
     pub fn put_pixel(&mut self, pixel: Vec3, j: usize, i: usize) {
-        self.pixels[j][i] = &self.pixels[j][i] + &pixel;
-        self.passes[j][i] += 1;
-        if self.passes[j][i] == self.max_passes {
+        self.put_samples(pixel, 1, j, i);
+    }
+
+    pub fn put_samples(&mut self, sum: Vec3, count: usize, j: usize, i: usize) {
+        let before = self.passes[j][i];
+        self.pixels[j][i] = self.pixels[j][i] + sum;
+        self.passes[j][i] += count;
+        if before < self.max_passes && self.passes[j][i] >= self.max_passes {
             self.finished += 1;
         }
     }
+
+    pub fn put_tile(&mut self, data: &TileData) {
+        let tile = &data.tile;
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let pixel = data.pixels[y * tile.width + x];
+                self.put_samples(pixel, data.passes, tile.y + y, tile.x + x);
+            }
+        }
+    }
 }
 
 impl Image {
@@ -56,25 +73,37 @@ impl Image {
         let data = vec![vec![Vec3::zero(); width]; height];
         Image {
             pixels: data,
+            alpha: None,
             width,
             height,
         }
     }
 
+    // Gray image of the opacity, fully opaque where the image has none
+    pub fn alpha_channel(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let a = self.alpha.as_ref().map_or(1.0, |alpha| alpha[y][x]);
+                image.put_pixel(Vec3::new(a, a, a), y, x);
+            }
+        }
+        image
+    }
+
     pub fn put_pixel(&mut self, color: Vec3, y: usize, x: usize) {
         self.pixels[y][x] = color;
     }
 
     pub fn get_pixel(&self, x: usize, y: usize) -> Vec3 {
-        self.pixels[x][y].clone()
+        self.pixels[x][y]
     }
     pub fn min_max(&self) -> (Float, Float) {
-        use std::f64::{MAX, MIN};
-        let (mut a, mut b) = (MAX, MIN);
+        let (mut a, mut b) = (Float::MAX, Float::MIN);
         for i in 0..self.width {
             for j in 0..self.height {
                 let pixel = &self.pixels[j][i];
-                for k in 0..2 {
+                for k in 0..3 {
                     if pixel[k] < a {
                         a = pixel[k]
                     }
@@ -86,25 +115,16 @@ impl Image {
         }
         (a, b)
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let (a, b) = self.min_max();
-        let mut s: String = String::new();
-        let mut counter = 0;
-        for i in 0..self.height {
-            for j in 0..self.width {
-                let vec = &self.pixels[i][j];
-                s.push_str(&vec.to_pixel_string(a, b));
-                // if j % 3 == 0 {
-                if counter == 2 {
-                    counter = 0;
-                    s.push_str("\n");
-                } else {
-                    counter += 1;
-                    s.push_str("\t");
-                }
-            }
+// Pixels as PPM text, sRGB encoded like the PPM writer with its default tone mapping
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data = self.to_rgb8(&Exposure::new(0.0));
+        for (i, pixel) in data.chunks(3).enumerate() {
+            let separator = if i % 3 == 2 { '\n' } else { '\t' };
+            write!(f, "{} {} {}{}", pixel[0], pixel[1], pixel[2], separator)?;
         }
-        s
+        Ok(())
     }
 }
@@ -1,3 +1,10 @@
 // Image mod
+mod hdr;
+#[allow(clippy::module_inception)]
 mod image;
+mod tonemap;
+mod writer;
+pub use hdr::*;
 pub use image::*;
+pub use tonemap::*;
+pub use writer::*;
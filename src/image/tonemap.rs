@@ -0,0 +1,164 @@
+// Tone mapping
+use crate::{clamp, Float, Vec3};
+
+pub trait ToneMapper: Send + Sync {
+    // Maps linear scene radiance to linear display values in [0, 1]
+    fn map(&self, color: &Vec3) -> Vec3;
+    fn to_string(&self) -> String;
+    fn print(&self) {
+        println!("{}", self.to_string());
+    }
+}
+
+pub fn luminance(color: &Vec3) -> Float {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn expose(color: &Vec3, exposure: Float) -> Vec3 {
+    color * (2.0 as Float).powf(exposure)
+}
+
+fn clamp_color(color: &Vec3) -> Vec3 {
+    Vec3::new(
+        clamp(color.x, 0.0, 1.0),
+        clamp(color.y, 0.0, 1.0),
+        clamp(color.z, 0.0, 1.0),
+    )
+}
+
+fn scale_luminance(color: &Vec3, mapped_luminance: Float) -> Vec3 {
+    let l = luminance(color);
+    if l <= 0.0 {
+        return Vec3::zero();
+    }
+    clamp_color(&(color * (mapped_luminance / l)))
+}
+
+// Linear scaling by 2^exposure, values above one are clipped
+pub struct Exposure {
+    pub exposure: Float,
+}
+
+pub struct Reinhard {
+    pub exposure: Float,
+    pub white: Option<Float>,
+}
+
+pub struct AcesFilmic {
+    pub exposure: Float,
+}
+
+pub struct Uncharted2 {
+    pub exposure: Float,
+    pub white: Float,
+}
+
+impl Exposure {
+    pub fn new(exposure: Float) -> Exposure {
+        Exposure { exposure }
+    }
+}
+
+impl Reinhard {
+    pub fn new(exposure: Float) -> Reinhard {
+        Reinhard {
+            exposure,
+            white: None,
+        }
+    }
+
+    // The extended operator maps a luminance of `white` to exactly one
+    pub fn extended(exposure: Float, white: Float) -> Reinhard {
+        Reinhard {
+            exposure,
+            white: Some(white),
+        }
+    }
+}
+
+impl AcesFilmic {
+    pub fn new(exposure: Float) -> AcesFilmic {
+        AcesFilmic { exposure }
+    }
+}
+
+impl Uncharted2 {
+    pub fn new(exposure: Float) -> Uncharted2 {
+        Uncharted2 {
+            exposure,
+            white: 11.2,
+        }
+    }
+
+    fn curve(x: Float) -> Float {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+}
+
+impl ToneMapper for Exposure {
+    fn map(&self, color: &Vec3) -> Vec3 {
+        clamp_color(&expose(color, self.exposure))
+    }
+
+    fn to_string(&self) -> String {
+        format!("Exposure tone mapping with {} stops", self.exposure)
+    }
+}
+
+impl ToneMapper for Reinhard {
+    fn map(&self, color: &Vec3) -> Vec3 {
+        let c = expose(color, self.exposure);
+        let l = luminance(&c);
+        let mapped = match self.white {
+            Some(white) => l * (1.0 + l / (white * white)) / (1.0 + l),
+            None => l / (1.0 + l),
+        };
+        scale_luminance(&c, mapped)
+    }
+
+    fn to_string(&self) -> String {
+        match self.white {
+            Some(white) => format!(
+                "Extended Reinhard tone mapping with {} stops and white point {}",
+                self.exposure, white
+            ),
+            None => format!("Reinhard tone mapping with {} stops", self.exposure),
+        }
+    }
+}
+
+impl ToneMapper for AcesFilmic {
+    // Narkowicz's fit of the ACES reference rendering transform
+    fn map(&self, color: &Vec3) -> Vec3 {
+        let c = expose(color, self.exposure);
+        let curve = |x: Float| {
+            let x = x * 0.6;
+            (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+        };
+        clamp_color(&Vec3::new(curve(c.x), curve(c.y), curve(c.z)))
+    }
+
+    fn to_string(&self) -> String {
+        format!("ACES filmic tone mapping with {} stops", self.exposure)
+    }
+}
+
+impl ToneMapper for Uncharted2 {
+    fn map(&self, color: &Vec3) -> Vec3 {
+        let c = expose(color, self.exposure + 1.0);
+        let white_scale = 1.0 / Uncharted2::curve(self.white);
+        clamp_color(&Vec3::new(
+            Uncharted2::curve(c.x) * white_scale,
+            Uncharted2::curve(c.y) * white_scale,
+            Uncharted2::curve(c.z) * white_scale,
+        ))
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Uncharted 2 filmic tone mapping with {} stops and white point {}",
+            self.exposure, self.white
+        )
+    }
+}
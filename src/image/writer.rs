@@ -0,0 +1,99 @@
+// Image writers
+use crate::{linear_to_srgb, to_byte, Exposure, Image, ToneMapper};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub trait ImageWriter {
+    fn write(&self, image: &Image, path: &Path) -> io::Result<()>;
+}
+
+pub struct PngWriter {
+    pub tone_mapper: Box<dyn ToneMapper>,
+}
+
+pub struct PpmWriter {
+    pub tone_mapper: Box<dyn ToneMapper>,
+}
+
+impl Default for PngWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PngWriter {
+    pub fn new() -> PngWriter {
+        PngWriter::with_tone_mapper(Box::new(Exposure::new(0.0)))
+    }
+
+    pub fn with_tone_mapper(tone_mapper: Box<dyn ToneMapper>) -> PngWriter {
+        PngWriter { tone_mapper }
+    }
+}
+
+impl Default for PpmWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PpmWriter {
+    pub fn new() -> PpmWriter {
+        PpmWriter::with_tone_mapper(Box::new(Exposure::new(0.0)))
+    }
+
+    pub fn with_tone_mapper(tone_mapper: Box<dyn ToneMapper>) -> PpmWriter {
+        PpmWriter { tone_mapper }
+    }
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, image: &Image, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&image.to_rgb8(self.tone_mapper.as_ref()))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, image: &Image, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "P3 \n{} {}\n255", image.width, image.height)?;
+        let data = image.to_rgb8(self.tone_mapper.as_ref());
+        for pixel in data.chunks(3) {
+            writeln!(file, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+        }
+        file.flush()
+    }
+}
+
+impl Image {
+    // Tone mapped, sRGB encoded 8-bit pixels, row by row
+    pub fn to_rgb8(&self, tone_mapper: &dyn ToneMapper) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.width * self.height * 3);
+        for row in self.pixels.iter() {
+            for pixel in row.iter() {
+                let mapped = tone_mapper.map(pixel);
+                data.push(to_byte(linear_to_srgb(mapped.x)));
+                data.push(to_byte(linear_to_srgb(mapped.y)));
+                data.push(to_byte(linear_to_srgb(mapped.z)));
+            }
+        }
+        data
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        PngWriter::new().write(self, path.as_ref())
+    }
+
+    pub fn write_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        PpmWriter::new().write(self, path.as_ref())
+    }
+}
@@ -2,15 +2,19 @@
 mod geometry;
 mod image;
 mod samples;
+mod sampling;
 mod threading;
 mod utils;
 mod accelerator;
 mod camera;
+mod loaders;
 
 pub use geometry::*;
 pub use image::*;
 pub use samples::*;
+pub use sampling::*;
 pub use threading::*;
 pub use utils::*;
 pub use accelerator::*;
 pub use camera::*;
+pub use loaders::*;
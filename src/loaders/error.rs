@@ -0,0 +1,37 @@
+// Load errors
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct LoadError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl LoadError {
+    pub fn new(file: &Path, line: usize, message: String) -> LoadError {
+        LoadError {
+            file: file.to_path_buf(),
+            line,
+            message,
+        }
+    }
+
+    pub fn io(file: &Path, err: std::io::Error) -> LoadError {
+        LoadError::new(file, 0, err.to_string())
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: {}", self.file.display(), self.line, self.message)
+        } else {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        }
+    }
+}
+
+impl Error for LoadError {}
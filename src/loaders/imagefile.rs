@@ -0,0 +1,336 @@
+// Image readers
+use crate::{Float, Image, LoadError, Vec3};
+use std::fs;
+use std::path::Path;
+
+// Reads an image, the format comes from the extension. HDR and PFM hold linear values,
+// 8 and 16 bit formats are returned as stored, scaled to [0, 1].
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("hdr") => load_hdr(path),
+        Some("pfm") => load_pfm(path),
+        Some("png") => load_png(path),
+        Some("ppm") | Some("pgm") => load_ppm(path),
+        _ => Err(LoadError::new(
+            path,
+            0,
+            "unsupported image format".to_string(),
+        )),
+    }
+}
+
+// Splits off the next line of a text header
+fn header_line<'a>(data: &'a [u8], offset: &mut usize) -> Option<&'a str> {
+    let start = *offset;
+    let end = start + data[start..].iter().position(|&b| b == b'\n')?;
+    *offset = end + 1;
+    std::str::from_utf8(&data[start..end])
+        .ok()
+        .map(|l| l.trim())
+}
+
+// Reads count whitespace separated tokens, skipping comments from # to the end of the line,
+// and leaves offset after the single whitespace byte that ends the header
+fn header_tokens(data: &[u8], offset: &mut usize, count: usize) -> Option<Vec<String>> {
+    let mut tokens = Vec::with_capacity(count);
+    while tokens.len() < count {
+        match data.get(*offset)? {
+            b'#' => {
+                while data.get(*offset).is_some_and(|&b| b != b'\n') {
+                    *offset += 1;
+                }
+            }
+            b if b.is_ascii_whitespace() => *offset += 1,
+            _ => {
+                let start = *offset;
+                while data.get(*offset).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    *offset += 1;
+                }
+                tokens.push(String::from_utf8_lossy(&data[start..*offset]).to_string());
+            }
+        }
+    }
+    *offset += 1;
+    Some(tokens)
+}
+
+// Radiance RGBE files with flat or run length encoded scanlines
+pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| LoadError::io(path, e))?;
+    let error = |message: &str| LoadError::new(path, 0, message.to_string());
+    let mut offset = 0;
+    match header_line(&data, &mut offset) {
+        Some(l) if l.starts_with("#?") => {}
+        _ => return Err(error("not a Radiance HDR file")),
+    }
+    loop {
+        let line = header_line(&data, &mut offset).ok_or_else(|| error("truncated header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(error(&format!("unsupported pixel format {}", format)));
+            }
+        }
+    }
+    let resolution = header_line(&data, &mut offset).ok_or_else(|| error("missing resolution"))?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (flip, height, width) = match fields.as_slice() {
+        [y, h, "+X", w] if *y == "-Y" || *y == "+Y" => (*y == "+Y", h.parse(), w.parse()),
+        _ => return Err(error("unsupported resolution line")),
+    };
+    let (height, width): (usize, usize) = match (height, width) {
+        (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+        _ => return Err(error("invalid image size")),
+    };
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for y in 0..height {
+        read_hdr_scanline(&data, &mut offset, &mut scanline)
+            .ok_or_else(|| error("truncated pixel data"))?;
+        let row = if flip { height - 1 - y } else { y };
+        for (x, rgbe) in scanline.iter().enumerate() {
+            image.put_pixel(rgbe_to_color(rgbe), row, x);
+        }
+    }
+    Ok(image)
+}
+
+fn read_hdr_scanline(data: &[u8], offset: &mut usize, scanline: &mut [[u8; 4]]) -> Option<()> {
+    let width = scanline.len();
+    let start = data.get(*offset..*offset + 4)?;
+    let encoded = (8..0x8000).contains(&width)
+        && start[0] == 2
+        && start[1] == 2
+        && ((start[2] as usize) << 8 | start[3] as usize) == width;
+    if !encoded {
+        return read_flat_scanline(data, offset, scanline);
+    }
+    *offset += 4;
+    // Each channel is stored separately as runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(*offset)? as usize;
+            *offset += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(*offset)?;
+                *offset += 1;
+                for pixel in scanline.get_mut(x..x + count)? {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 {
+                    return None;
+                }
+                let values = data.get(*offset..*offset + count)?;
+                *offset += count;
+                for (pixel, &value) in scanline.get_mut(x..x + count)?.iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Some(())
+}
+
+// Plain pixels, where a pixel of 1, 1, 1 repeats the previous one
+fn read_flat_scanline(data: &[u8], offset: &mut usize, scanline: &mut [[u8; 4]]) -> Option<()> {
+    let mut x = 0;
+    let mut shift = 0;
+    while x < scanline.len() {
+        let bytes = data.get(*offset..*offset + 4)?;
+        *offset += 4;
+        if bytes[0] == 1 && bytes[1] == 1 && bytes[2] == 1 && x > 0 {
+            let count = (bytes[3] as usize) << shift;
+            let previous = scanline[x - 1];
+            for pixel in scanline.get_mut(x..x + count)? {
+                *pixel = previous;
+            }
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            x += 1;
+            shift = 0;
+        }
+    }
+    Some(())
+}
+
+fn rgbe_to_color(rgbe: &[u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+    let scale = (2.0 as Float).powi(rgbe[3] as i32 - (128 + 8));
+    Vec3::new(
+        (rgbe[0] as Float + 0.5) * scale,
+        (rgbe[1] as Float + 0.5) * scale,
+        (rgbe[2] as Float + 0.5) * scale,
+    )
+}
+
+// Portable float maps in color or grayscale, as written by PfmWriter
+pub fn load_pfm<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| LoadError::io(path, e))?;
+    let error = |message: &str| LoadError::new(path, 0, message.to_string());
+    // The header holds the magic, width, height and scale separated by whitespace
+    let mut offset = 0;
+    let tokens = header_tokens(&data, &mut offset, 4).ok_or_else(|| error("truncated header"))?;
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(error("not a PFM file")),
+    };
+    let (width, height, scale): (usize, usize, f32) =
+        match (tokens[1].parse(), tokens[2].parse(), tokens[3].parse()) {
+            (Ok(w), Ok(h), Ok(s)) if w > 0 && h > 0 && s != 0.0 => (w, h, s),
+            _ => return Err(error("invalid header")),
+        };
+    let pixels = data
+        .get(offset..offset + width * height * channels * 4)
+        .ok_or_else(|| error("truncated pixel data"))?;
+    let values: Vec<Float> = pixels
+        .chunks(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            // A negative scale marks little endian data
+            let value = if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            value as Float
+        })
+        .collect();
+    let mut image = Image::new(width, height);
+    // Scanlines are stored from the bottom of the image to the top
+    for (y, row) in values.chunks(width * channels).enumerate() {
+        for (x, v) in row.chunks(channels).enumerate() {
+            let color = if channels == 3 {
+                Vec3::new(v[0], v[1], v[2])
+            } else {
+                Vec3::new(v[0], v[0], v[0])
+            };
+            image.put_pixel(color, height - 1 - y, x);
+        }
+    }
+    Ok(image)
+}
+
+// 8 or 16 bit PNG files of any color type, alpha is kept apart from the colors
+pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+    let path = path.as_ref();
+    let error = |message: String| LoadError::new(path, 0, message);
+    let file = fs::File::open(path).map_err(|e| LoadError::io(path, e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| error(e.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| error(e.to_string()))?;
+    let channels = info.color_type.samples();
+    let (bytes, max) = match info.bit_depth {
+        png::BitDepth::Sixteen => (2, 65535.0),
+        _ => (1, 255.0),
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut image = Image::new(width, height);
+    // Gray and RGB with alpha carry it as their last sample
+    let has_alpha = channels == 2 || channels == 4;
+    let mut alpha = vec![vec![1.0; width]; height];
+    for (y, row) in buffer.chunks(info.line_size).take(height).enumerate() {
+        for (x, pixel) in row.chunks(channels * bytes).take(width).enumerate() {
+            let sample = |c: usize| {
+                let value = if bytes == 2 {
+                    u16::from_be_bytes([pixel[2 * c], pixel[2 * c + 1]]) as Float
+                } else {
+                    pixel[c] as Float
+                };
+                value / max
+            };
+            // Gray with or without alpha has one color sample, RGB with or without alpha three
+            let color = if channels < 3 {
+                Vec3::new(sample(0), sample(0), sample(0))
+            } else {
+                Vec3::new(sample(0), sample(1), sample(2))
+            };
+            image.put_pixel(color, y, x);
+            if has_alpha {
+                alpha[y][x] = sample(channels - 1);
+            }
+        }
+    }
+    if has_alpha {
+        image.alpha = Some(alpha);
+    }
+    Ok(image)
+}
+
+// Binary and plain netpbm color (P6, P3) and gray (P5, P2) maps
+pub fn load_ppm<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| LoadError::io(path, e))?;
+    let error = |message: &str| LoadError::new(path, 0, message.to_string());
+    let mut offset = 0;
+    let tokens = header_tokens(&data, &mut offset, 4).ok_or_else(|| error("truncated header"))?;
+    let (channels, binary) = match tokens[0].as_str() {
+        "P3" => (3, false),
+        "P6" => (3, true),
+        "P2" => (1, false),
+        "P5" => (1, true),
+        _ => return Err(error("not a PPM or PGM file")),
+    };
+    let (width, height, max): (usize, usize, usize) =
+        match (tokens[1].parse(), tokens[2].parse(), tokens[3].parse()) {
+            (Ok(w), Ok(h), Ok(m)) if w > 0 && h > 0 && m > 0 && m < 65536 => (w, h, m),
+            _ => return Err(error("invalid header")),
+        };
+    let count = width * height * channels;
+    let values: Vec<usize> = if binary {
+        // Samples take two big endian bytes when the maximum does not fit in one
+        let bytes = if max > 255 { 2 } else { 1 };
+        let samples = data
+            .get(offset..offset + count * bytes)
+            .ok_or_else(|| error("truncated pixel data"))?;
+        samples
+            .chunks(bytes)
+            .map(|b| b.iter().fold(0, |value, &byte| value << 8 | byte as usize))
+            .collect()
+    } else {
+        // The plain header ends at the whitespace already skipped
+        let text = String::from_utf8_lossy(&data[(offset - 1).min(data.len())..]);
+        let values: Vec<usize> = text
+            .split_whitespace()
+            .take(count)
+            .map(|t| t.parse().map_err(|_| error("invalid sample")))
+            .collect::<Result<_, _>>()?;
+        if values.len() < count {
+            return Err(error("truncated pixel data"));
+        }
+        values
+    };
+    let mut image = Image::new(width, height);
+    for (i, pixel) in values.chunks(channels).enumerate() {
+        let sample = |c: usize| pixel[c].min(max) as Float / max as Float;
+        let color = if channels == 3 {
+            Vec3::new(sample(0), sample(1), sample(2))
+        } else {
+            Vec3::new(sample(0), sample(0), sample(0))
+        };
+        image.put_pixel(color, i / width, i % width);
+    }
+    Ok(image)
+}
@@ -0,0 +1,10 @@
+// Loaders
+mod error;
+mod imagefile;
+mod obj;
+mod scenefile;
+
+pub use error::*;
+pub use imagefile::*;
+pub use obj::*;
+pub use scenefile::*;
@@ -0,0 +1,366 @@
+// Wavefront OBJ and MTL loader
+use crate::{
+    max, BaseMaterial, ConstantTexture, Dielectric, DiffuseLight, Float, Hittable, LoadError,
+    Material, MeshData, Metal, TriangleMesh, Vec3,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+pub type MaterialLibrary = HashMap<String, Arc<dyn BaseMaterial>>;
+
+struct MtlEntry {
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    ns: Float,
+    ni: Float,
+    dissolve: Float,
+    illum: i32,
+}
+
+impl MtlEntry {
+    fn new() -> MtlEntry {
+        MtlEntry {
+            kd: Vec3::new(0.8, 0.8, 0.8),
+            ks: Vec3::zero(),
+            ke: Vec3::zero(),
+            ns: 0.0,
+            ni: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+
+    fn to_material(&self) -> Arc<dyn BaseMaterial> {
+        if max_component(&self.ke) > 0.0 {
+            return Arc::new(DiffuseLight::new(self.ke));
+        }
+        // Refraction illumination models or partial dissolve describe glass
+        if self.dissolve < 1.0 || self.illum == 4 || self.illum == 6 || self.illum == 7 {
+            return Arc::new(Dielectric::new(max(self.ni, 1.0)));
+        }
+        if max_component(&self.ks) > max_component(&self.kd) {
+            // Blinn-Phong exponent to a roughness-like fuzz value
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt();
+            return Arc::new(Metal::new(Arc::new(ConstantTexture::new(self.ks)), fuzz));
+        }
+        Arc::new(Material::new(self.kd))
+    }
+}
+
+fn max_component(v: &Vec3) -> Float {
+    v.x.max(v.y).max(v.z)
+}
+
+fn read_source(path: &Path) -> Result<String, LoadError> {
+    fs::read_to_string(path).map_err(|e| LoadError::io(path, e))
+}
+
+fn parse_floats(
+    path: &Path,
+    line: usize,
+    args: &[&str],
+    min: usize,
+) -> Result<Vec<Float>, LoadError> {
+    if args.len() < min {
+        return Err(LoadError::new(
+            path,
+            line,
+            format!("expected at least {} numbers, found {}", min, args.len()),
+        ));
+    }
+    args.iter()
+        .map(|a| {
+            a.parse::<Float>()
+                .map_err(|_| LoadError::new(path, line, format!("invalid number '{}'", a)))
+        })
+        .collect()
+}
+
+fn parse_vec3(path: &Path, line: usize, args: &[&str]) -> Result<Vec3, LoadError> {
+    let v = parse_floats(path, line, args, 3)?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<MaterialLibrary, LoadError> {
+    let path = path.as_ref();
+    let source = read_source(path)?;
+    parse_mtl(&source, path)
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<MaterialLibrary, LoadError> {
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut tokens = raw.split('#').next().unwrap().split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(LoadError::new(
+                    path,
+                    line,
+                    "newmtl without a name".to_string(),
+                ));
+            }
+            entries.push((args.join(" "), MtlEntry::new()));
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some((_, e)) => e,
+            None => {
+                return Err(LoadError::new(
+                    path,
+                    line,
+                    format!("'{}' before any newmtl", keyword),
+                ))
+            }
+        };
+        match keyword {
+            "Kd" => entry.kd = parse_vec3(path, line, &args)?,
+            "Ks" => entry.ks = parse_vec3(path, line, &args)?,
+            "Ke" => entry.ke = parse_vec3(path, line, &args)?,
+            "Ns" => entry.ns = parse_floats(path, line, &args, 1)?[0],
+            "Ni" => entry.ni = parse_floats(path, line, &args, 1)?[0],
+            "d" => entry.dissolve = parse_floats(path, line, &args, 1)?[0],
+            "Tr" => entry.dissolve = 1.0 - parse_floats(path, line, &args, 1)?[0],
+            "illum" => {
+                entry.illum = args
+                    .first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| LoadError::new(path, line, "invalid illum model".to_string()))?
+            }
+            _ => {}
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.to_material()))
+        .collect())
+}
+
+// Vertex reference of a face: position, texcoord and normal indices
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+struct MeshBuilder {
+    material: Arc<dyn BaseMaterial>,
+    faces: Vec<[FaceVertex; 3]>,
+}
+
+impl MeshBuilder {
+    fn build(
+        &self,
+        positions: &[Vec3],
+        uvs: &[(Float, Float)],
+        normals: &[Vec3],
+    ) -> Result<TriangleMesh, String> {
+        let with_uvs = self.faces.iter().flatten().all(|v| v.1.is_some());
+        let with_normals = self.faces.iter().flatten().all(|v| v.2.is_some());
+        let mut remap: HashMap<FaceVertex, usize> = HashMap::new();
+        let mut data = MeshData::new(Vec::new(), Vec::with_capacity(self.faces.len() * 3));
+        for vertex in self.faces.iter().flatten() {
+            let key = (
+                vertex.0,
+                if with_uvs { vertex.1 } else { None },
+                if with_normals { vertex.2 } else { None },
+            );
+            let next = data.positions.len();
+            let index = *remap.entry(key).or_insert(next);
+            if index == next {
+                data.positions.push(positions[key.0]);
+                if let Some(t) = key.1 {
+                    data.uvs.push(uvs[t]);
+                }
+                if let Some(n) = key.2 {
+                    data.normals.push(normals[n]);
+                }
+            }
+            data.indices.push(index);
+        }
+        TriangleMesh::from_data(self.material.clone(), data)
+    }
+}
+
+fn resolve_index(
+    path: &Path,
+    line: usize,
+    token: &str,
+    count: usize,
+    kind: &str,
+) -> Result<usize, LoadError> {
+    let value: i64 = token
+        .parse()
+        .map_err(|_| LoadError::new(path, line, format!("invalid {} index '{}'", kind, token)))?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::new(
+            path,
+            line,
+            format!("{} index {} out of range ({} defined)", kind, value, count),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Arc<dyn Hittable>>, LoadError> {
+    let path = path.as_ref();
+    let source = read_source(path)?;
+    parse_obj(&source, path)
+}
+
+// Loads the geometry only, every face uses the given material and material libraries are skipped
+pub fn load_obj_with_material<P: AsRef<Path>>(
+    path: P,
+    material: Arc<dyn BaseMaterial>,
+) -> Result<Vec<Arc<dyn Hittable>>, LoadError> {
+    let path = path.as_ref();
+    let source = read_source(path)?;
+    parse_obj_with_material(&source, path, Some(material))
+}
+
+pub fn parse_obj(source: &str, path: &Path) -> Result<Vec<Arc<dyn Hittable>>, LoadError> {
+    parse_obj_with_material(source, path, None)
+}
+
+pub fn parse_obj_with_material(
+    source: &str,
+    path: &Path,
+    material_override: Option<Arc<dyn BaseMaterial>>,
+) -> Result<Vec<Arc<dyn Hittable>>, LoadError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut library: MaterialLibrary = HashMap::new();
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(Float, Float)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut builders: Vec<MeshBuilder> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut material: Arc<dyn BaseMaterial> = match &material_override {
+        Some(m) => m.clone(),
+        None => Arc::new(Material::new(Vec3::new(0.8, 0.8, 0.8))),
+    };
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut tokens = raw.split('#').next().unwrap().split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => positions.push(parse_vec3(path, line, &args)?),
+            "vn" => normals.push(parse_vec3(path, line, &args)?.normalize()),
+            "vt" => {
+                let t = parse_floats(path, line, &args, 1)?;
+                uvs.push((t[0], if t.len() > 1 { t[1] } else { 0.0 }));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(LoadError::new(
+                        path,
+                        line,
+                        format!("face needs at least 3 vertices, found {}", args.len()),
+                    ));
+                }
+                let mut polygon: Vec<FaceVertex> = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let mut parts = arg.split('/');
+                    let v = resolve_index(
+                        path,
+                        line,
+                        parts.next().unwrap(),
+                        positions.len(),
+                        "vertex",
+                    )?;
+                    let t = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(path, line, s, uvs.len(), "texcoord")?)
+                        }
+                        _ => None,
+                    };
+                    let n = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(path, line, s, normals.len(), "normal")?)
+                        }
+                        _ => None,
+                    };
+                    polygon.push((v, t, n));
+                }
+                let builder = match current {
+                    Some(i) => i,
+                    None => {
+                        builders.push(MeshBuilder {
+                            material: material.clone(),
+                            faces: Vec::new(),
+                        });
+                        builders.len() - 1
+                    }
+                };
+                current = Some(builder);
+                // Fan triangulation of convex n-gons
+                for i in 1..polygon.len() - 1 {
+                    builders[builder]
+                        .faces
+                        .push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            "g" | "o" => current = None,
+            "usemtl" | "mtllib" if material_override.is_some() => {}
+            "usemtl" => {
+                let name = args.join(" ");
+                material = match library.get(&name) {
+                    Some(m) => m.clone(),
+                    None => {
+                        return Err(LoadError::new(
+                            path,
+                            line,
+                            format!("unknown material '{}'", name),
+                        ))
+                    }
+                };
+                current = None;
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(LoadError::new(
+                        path,
+                        line,
+                        "mtllib without a file".to_string(),
+                    ));
+                }
+                for file in args.iter() {
+                    let mtl_path = base_dir.join(file);
+                    let mtl_source = fs::read_to_string(&mtl_path).map_err(|e| {
+                        LoadError::new(
+                            path,
+                            line,
+                            format!("cannot read {}: {}", mtl_path.display(), e),
+                        )
+                    })?;
+                    library.extend(parse_mtl(&mtl_source, &mtl_path)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    builders
+        .iter()
+        .filter(|b| !b.faces.is_empty())
+        .map(|b| {
+            let mesh = b
+                .build(&positions, &uvs, &normals)
+                .map_err(|e| LoadError::new(path, 0, e))?;
+            Ok(Arc::new(mesh) as Arc<dyn Hittable>)
+        })
+        .collect()
+}
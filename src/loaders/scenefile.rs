@@ -0,0 +1,1028 @@
+// Scene description files
+use crate::{
+    load_image, load_obj, load_obj_with_material, sun_direction, AnimatedInstance,
+    AnimatedTransform, BaseMaterial, BoundingBox, BumpMap, Bvh, Camera, CheckerTexture, ColorRamp,
+    Conductor, ConstantEnvironment, ConstantMedium, ConstantTexture, Cutout, Dielectric,
+    DiffuseLight, Environment, EnvironmentMap, Float, Fog, GradientBlend, GradientEnvironment,
+    Hittable, ImageTexture, Isotropic, LoadError, MarbleTexture, Material, Metal, MovingSphere,
+    NoiseTexture, NormalMap, Object, Principled, RoughDielectric, Scene, Sphere, SunDisc, SunSky,
+    Texture, TextureFilter, Transform, TransformedInstance, Triangle, TurbulenceTexture, Vec3,
+    WoodTexture, WrapMode, SUN_ANGULAR_RADIUS,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::value::{Table, Value};
+
+// A parsed scene along with the names it declared
+pub struct SceneFile {
+    pub scene: Scene,
+    pub textures: Vec<String>,
+    pub materials: Vec<String>,
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, LoadError> {
+    Ok(load_scene_file(path)?.scene)
+}
+
+pub fn load_scene_file<P: AsRef<Path>>(path: P) -> Result<SceneFile, LoadError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+    parse_scene_file(&source, path)
+}
+
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, LoadError> {
+    Ok(parse_scene_file(source, path)?.scene)
+}
+
+pub fn parse_scene_file(source: &str, path: &Path) -> Result<SceneFile, LoadError> {
+    let root: Value = source.parse().map_err(|e: toml::de::Error| {
+        let line = e.line_col().map_or(0, |(line, _)| line + 1);
+        LoadError::new(path, line, e.to_string())
+    })?;
+    let mut parser = SceneParser {
+        path,
+        base_dir: path.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+        texture_declarations: None,
+        material_declarations: None,
+        textures: RefCell::new(HashMap::new()),
+        materials: RefCell::new(HashMap::new()),
+        resolving: RefCell::new(HashSet::new()),
+        meshes: HashMap::new(),
+    };
+    let scene = parser.parse(&root)?;
+    let mut textures: Vec<String> = parser.textures.borrow().keys().cloned().collect();
+    let mut materials: Vec<String> = parser.materials.borrow().keys().cloned().collect();
+    textures.sort();
+    materials.sort();
+    Ok(SceneFile {
+        scene,
+        textures,
+        materials,
+    })
+}
+
+enum Placement {
+    Fixed,
+    Static(Box<Transform>),
+    Animated(Box<AnimatedTransform>),
+}
+
+// Meshes already loaded are keyed by file and material override and shared by their instances
+type MeshKey = (PathBuf, Option<String>);
+
+struct SceneParser<'a> {
+    path: &'a Path,
+    base_dir: PathBuf,
+    texture_declarations: Option<&'a Table>,
+    material_declarations: Option<&'a Table>,
+    // Declarations are parsed on first use, so they may name ones that come after them
+    textures: RefCell<HashMap<String, Arc<dyn Texture>>>,
+    materials: RefCell<HashMap<String, Arc<dyn BaseMaterial>>>,
+    // Declarations being parsed, to catch ones that end up referring to themselves
+    resolving: RefCell<HashSet<String>>,
+    meshes: HashMap<MeshKey, Vec<Arc<dyn Hittable>>>,
+}
+
+impl<'a> SceneParser<'a> {
+    fn error(&self, context: &str, message: String) -> LoadError {
+        LoadError::new(self.path, 0, format!("{}: {}", context, message))
+    }
+
+    fn table<'v>(&self, value: &'v Value, context: &str) -> Result<&'v Table, LoadError> {
+        value
+            .as_table()
+            .ok_or_else(|| self.error(context, "expected a table".to_string()))
+    }
+
+    fn number(&self, value: &Value, context: &str) -> Result<Float, LoadError> {
+        match value {
+            Value::Float(f) => Ok(*f),
+            Value::Integer(i) => Ok(*i as Float),
+            _ => Err(self.error(context, "expected a number".to_string())),
+        }
+    }
+
+    fn vector(&self, value: &Value, context: &str) -> Result<Vec3, LoadError> {
+        match value.as_array() {
+            Some(a) if a.len() == 3 => Ok(Vec3::new(
+                self.number(&a[0], context)?,
+                self.number(&a[1], context)?,
+                self.number(&a[2], context)?,
+            )),
+            _ => Err(self.error(context, "expected an array of 3 numbers".to_string())),
+        }
+    }
+
+    fn string<'v>(&self, value: &'v Value, context: &str) -> Result<&'v str, LoadError> {
+        value
+            .as_str()
+            .ok_or_else(|| self.error(context, "expected a string".to_string()))
+    }
+
+    fn required<'v>(
+        &self,
+        table: &'v Table,
+        key: &str,
+        context: &str,
+    ) -> Result<&'v Value, LoadError> {
+        table
+            .get(key)
+            .ok_or_else(|| self.error(context, format!("missing '{}'", key)))
+    }
+
+    fn get_number(&self, table: &Table, key: &str, context: &str) -> Result<Float, LoadError> {
+        self.number(
+            self.required(table, key, context)?,
+            &format!("{}.{}", context, key),
+        )
+    }
+
+    fn get_number_or(
+        &self,
+        table: &Table,
+        key: &str,
+        default: Float,
+        context: &str,
+    ) -> Result<Float, LoadError> {
+        match table.get(key) {
+            Some(v) => self.number(v, &format!("{}.{}", context, key)),
+            None => Ok(default),
+        }
+    }
+
+    fn get_vector(&self, table: &Table, key: &str, context: &str) -> Result<Vec3, LoadError> {
+        self.vector(
+            self.required(table, key, context)?,
+            &format!("{}.{}", context, key),
+        )
+    }
+
+    fn get_string<'v>(
+        &self,
+        table: &'v Table,
+        key: &str,
+        context: &str,
+    ) -> Result<&'v str, LoadError> {
+        self.string(
+            self.required(table, key, context)?,
+            &format!("{}.{}", context, key),
+        )
+    }
+
+    fn get_string_or<'v>(
+        &self,
+        table: &'v Table,
+        key: &str,
+        default: &'v str,
+        context: &str,
+    ) -> Result<&'v str, LoadError> {
+        match table.get(key) {
+            Some(v) => self.string(v, &format!("{}.{}", context, key)),
+            None => Ok(default),
+        }
+    }
+
+    fn get_bool_or(
+        &self,
+        table: &Table,
+        key: &str,
+        default: bool,
+        context: &str,
+    ) -> Result<bool, LoadError> {
+        match table.get(key) {
+            Some(v) => v.as_bool().ok_or_else(|| {
+                self.error(
+                    &format!("{}.{}", context, key),
+                    "expected a boolean".to_string(),
+                )
+            }),
+            None => Ok(default),
+        }
+    }
+
+    // A texture is either the name of a declared texture or an inline color
+    fn texture(&self, value: &Value, context: &str) -> Result<Arc<dyn Texture>, LoadError> {
+        if let Some(name) = value.as_str() {
+            return self.declared_texture(name, context);
+        }
+        Ok(Arc::new(ConstantTexture::new(self.vector(value, context)?)))
+    }
+
+    fn get_texture(
+        &self,
+        table: &Table,
+        key: &str,
+        context: &str,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        self.texture(
+            self.required(table, key, context)?,
+            &format!("{}.{}", context, key),
+        )
+    }
+
+    fn get_texture_or(
+        &self,
+        table: &Table,
+        key: &str,
+        default: Vec3,
+        context: &str,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        match table.get(key) {
+            Some(v) => self.texture(v, &format!("{}.{}", context, key)),
+            None => Ok(Arc::new(ConstantTexture::new(default))),
+        }
+    }
+
+    // A single number stands for a constant texture of that value
+    fn get_scalar_texture_or(
+        &self,
+        table: &Table,
+        key: &str,
+        default: Float,
+        context: &str,
+    ) -> Result<Arc<dyn Texture>, LoadError> {
+        match table.get(key) {
+            Some(Value::Float(_)) | Some(Value::Integer(_)) | None => {
+                let value = self.get_number_or(table, key, default, context)?;
+                Ok(Arc::new(ConstantTexture::new(Vec3::new(
+                    value, value, value,
+                ))))
+            }
+            Some(v) => self.texture(v, &format!("{}.{}", context, key)),
+        }
+    }
+
+    fn material(&self, value: &Value, context: &str) -> Result<Arc<dyn BaseMaterial>, LoadError> {
+        let name = self.string(value, context)?;
+        self.declared_material(name, context)
+    }
+
+    fn declared_texture(&self, name: &str, context: &str) -> Result<Arc<dyn Texture>, LoadError> {
+        self.declared(
+            "texture",
+            name,
+            context,
+            self.texture_declarations,
+            &self.textures,
+            Self::parse_texture,
+        )
+    }
+
+    fn declared_material(
+        &self,
+        name: &str,
+        context: &str,
+    ) -> Result<Arc<dyn BaseMaterial>, LoadError> {
+        self.declared(
+            "material",
+            name,
+            context,
+            self.material_declarations,
+            &self.materials,
+            Self::parse_material,
+        )
+    }
+
+    fn declared<T: ?Sized>(
+        &self,
+        kind: &str,
+        name: &str,
+        context: &str,
+        declarations: Option<&Table>,
+        parsed: &RefCell<HashMap<String, Arc<T>>>,
+        parse: fn(&Self, &Table, &str) -> Result<Arc<T>, LoadError>,
+    ) -> Result<Arc<T>, LoadError> {
+        if let Some(value) = parsed.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        let value = declarations
+            .and_then(|d| d.get(name))
+            .ok_or_else(|| self.error(context, format!("unknown {} '{}'", kind, name)))?;
+        let declaration = format!("{}s.{}", kind, name);
+        let table = self.table(value, &declaration)?;
+        if !self.resolving.borrow_mut().insert(declaration.clone()) {
+            return Err(self.error(context, format!("{} '{}' refers to itself", kind, name)));
+        }
+        let result = parse(self, table, &declaration);
+        self.resolving.borrow_mut().remove(&declaration);
+        let value = result?;
+        parsed.borrow_mut().insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    fn get_material(
+        &self,
+        table: &Table,
+        key: &str,
+        context: &str,
+    ) -> Result<Arc<dyn BaseMaterial>, LoadError> {
+        self.material(
+            self.required(table, key, context)?,
+            &format!("{}.{}", context, key),
+        )
+    }
+
+    // Materials that wrap another take it inline or by the name of a declared material
+    fn get_wrapped_material(
+        &self,
+        table: &Table,
+        context: &str,
+    ) -> Result<Arc<dyn BaseMaterial>, LoadError> {
+        match self.required(table, "material", context)? {
+            Value::Table(inner) => self.parse_material(inner, &format!("{}.material", context)),
+            _ => self.get_material(table, "material", context),
+        }
+    }
+
+    fn parse(&mut self, root: &'a Value) -> Result<Scene, LoadError> {
+        let root = self.table(root, "scene")?;
+        for key in root.keys() {
+            match key.as_str() {
+                "camera" | "environment" | "fog" | "textures" | "materials" | "objects" => {}
+                _ => return Err(self.error(key, "unknown section".to_string())),
+            }
+        }
+        let mut scene = Scene::new();
+        if let Some(camera) = root.get("camera") {
+            scene.camera = self.parse_camera(camera)?;
+        }
+        if let Some(environment) = root.get("environment") {
+            scene.set_environment(self.parse_environment(environment)?);
+        }
+        if let Some(fog) = root.get("fog") {
+            scene.set_fog(self.parse_fog(fog)?);
+        }
+        if let Some(textures) = root.get("textures") {
+            self.texture_declarations = Some(self.table(textures, "textures")?);
+        }
+        if let Some(materials) = root.get("materials") {
+            self.material_declarations = Some(self.table(materials, "materials")?);
+        }
+        // Every declaration is parsed, even the ones nothing refers to
+        for name in self.texture_declarations.iter().flat_map(|d| d.keys()) {
+            self.declared_texture(name, "textures")?;
+        }
+        for name in self.material_declarations.iter().flat_map(|d| d.keys()) {
+            self.declared_material(name, "materials")?;
+        }
+        if let Some(objects) = root.get("objects") {
+            let objects = objects
+                .as_array()
+                .ok_or_else(|| self.error("objects", "expected an array of tables".to_string()))?;
+            for (index, value) in objects.iter().enumerate() {
+                let context = format!("objects[{}]", index);
+                self.parse_object(&mut scene, self.table(value, &context)?, &context)?;
+            }
+        }
+        scene.build_accelerator();
+        Ok(scene)
+    }
+
+    fn parse_camera(&self, value: &Value) -> Result<Camera, LoadError> {
+        let context = "camera";
+        let table = self.table(value, context)?;
+        let up = match table.get("up") {
+            Some(v) => self.vector(v, "camera.up")?,
+            None => Vec3::new(0.0, 1.0, 0.0),
+        };
+        let mut camera = Camera::new(
+            self.get_vector(table, "position", context)?,
+            self.get_vector(table, "look_at", context)?,
+            up,
+        );
+        camera.set_angle(self.get_number_or(table, "angle", 55.0, context)?);
+        camera.set_aperture(self.get_number_or(table, "aperture", 0.0, context)?);
+        camera.focus_distance = self.get_number_or(table, "focus_distance", 1.0, context)?;
+        camera.set_shutter(
+            self.get_number_or(table, "shutter_open", 0.0, context)?,
+            self.get_number_or(table, "shutter_close", 0.0, context)?,
+        );
+        Ok(camera)
+    }
+
+    fn parse_environment(&self, value: &Value) -> Result<Arc<dyn Environment>, LoadError> {
+        let context = "environment";
+        let table = self.table(value, context)?;
+        match self.get_string(table, "type", context)? {
+            "constant" => Ok(Arc::new(ConstantEnvironment::new(
+                self.get_vector(table, "color", context)?,
+            ))),
+            "gradient" => {
+                let sky = GradientEnvironment::sky();
+                let horizon = match table.get("horizon") {
+                    Some(v) => self.vector(v, "environment.horizon")?,
+                    None => sky.horizon,
+                };
+                let zenith = match table.get("zenith") {
+                    Some(v) => self.vector(v, "environment.zenith")?,
+                    None => sky.zenith,
+                };
+                let blend_name = self.get_string_or(table, "blend", "add", context)?;
+                let blend = GradientBlend::from_name(blend_name).ok_or_else(|| {
+                    self.error(context, format!("unknown gradient blend '{}'", blend_name))
+                })?;
+                Ok(Arc::new(GradientEnvironment::new(horizon, zenith, blend)))
+            }
+            "map" => {
+                let file = self.base_dir.join(self.get_string(table, "file", context)?);
+                Ok(Arc::new(EnvironmentMap::new(
+                    load_image(&file)?,
+                    self.get_number_or(table, "intensity", 1.0, context)?,
+                    self.get_number_or(table, "rotation", 0.0, context)?
+                        .to_radians(),
+                )))
+            }
+            "sky" => {
+                let turbidity = self.get_number_or(table, "turbidity", 3.0, context)?;
+                if !(1.7..=10.0).contains(&turbidity) {
+                    let message = "must be between 1.7 and 10".to_string();
+                    return Err(self.error("environment.turbidity", message));
+                }
+                let direction = match table.get("sun_direction") {
+                    Some(v) => self.vector(v, "environment.sun_direction")?.normalize(),
+                    None => sun_direction(
+                        self.get_number_or(table, "elevation", 45.0, context)?,
+                        self.get_number_or(table, "azimuth", 0.0, context)?,
+                    ),
+                };
+                let mut sky = SunSky::new(
+                    direction,
+                    turbidity,
+                    self.get_number_or(table, "intensity", 1.0, context)?,
+                );
+                let sun_radius =
+                    self.get_number_or(table, "sun_radius", SUN_ANGULAR_RADIUS, context)?;
+                sky.sun = if self.get_bool_or(table, "sun", true, context)? {
+                    Some(SunDisc::new(direction, sun_radius, turbidity))
+                } else {
+                    None
+                };
+                Ok(Arc::new(sky))
+            }
+            other => Err(self.error(context, format!("unknown environment type '{}'", other))),
+        }
+    }
+
+    fn parse_fog(&self, value: &Value) -> Result<Fog, LoadError> {
+        let context = "fog";
+        let table = self.table(value, context)?;
+        let density = self.get_number(table, "density", context)?;
+        if density <= 0.0 {
+            return Err(self.error("fog.density", "must be positive".to_string()));
+        }
+        let albedo = match table.get("albedo") {
+            Some(v) => self.vector(v, "fog.albedo")?,
+            None => Vec3::new(1.0, 1.0, 1.0),
+        };
+        // Without a box the fog is unbounded and hides the environment
+        let bounds = match (table.get("min"), table.get("max")) {
+            (Some(min), Some(max)) => Some(BoundingBox::new(
+                self.vector(min, "fog.min")?,
+                self.vector(max, "fog.max")?,
+            )),
+            (None, None) => None,
+            _ => return Err(self.error(context, "needs both min and max or neither".to_string())),
+        };
+        Ok(Fog::new(density, albedo, bounds))
+    }
+
+    fn parse_texture(&self, table: &Table, context: &str) -> Result<Arc<dyn Texture>, LoadError> {
+        match self.get_string(table, "type", context)? {
+            "constant" => Ok(Arc::new(ConstantTexture::new(
+                self.get_vector(table, "color", context)?,
+            ))),
+            "image" => {
+                let file = self.base_dir.join(self.get_string(table, "file", context)?);
+                let filter_name = self.get_string_or(table, "filter", "bilinear", context)?;
+                let filter = TextureFilter::from_name(filter_name).ok_or_else(|| {
+                    self.error(context, format!("unknown filter '{}'", filter_name))
+                })?;
+                let wrap_name = self.get_string_or(table, "wrap", "repeat", context)?;
+                let wrap = WrapMode::from_name(wrap_name).ok_or_else(|| {
+                    self.error(context, format!("unknown wrap mode '{}'", wrap_name))
+                })?;
+                // 8 bit images hold sRGB encoded colors unless told otherwise
+                let extension = file
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_lowercase());
+                let encoded = !matches!(extension.as_deref(), Some("hdr") | Some("pfm"));
+                // The alpha channel reads as a gray image of linear opacities
+                let alpha = match self.get_string_or(table, "channel", "color", context)? {
+                    "color" => false,
+                    "alpha" => true,
+                    other => {
+                        return Err(self.error(context, format!("unknown channel '{}'", other)))
+                    }
+                };
+                let image = load_image(&file)?;
+                let (image, encoded) = if alpha {
+                    (image.alpha_channel(), false)
+                } else {
+                    (image, encoded)
+                };
+                let srgb = self.get_bool_or(table, "srgb", encoded, context)?;
+                Ok(Arc::new(ImageTexture::new(image, filter, wrap, srgb)))
+            }
+            "checker" => Ok(Arc::new(CheckerTexture::new(
+                self.get_texture(table, "even", context)?,
+                self.get_texture(table, "odd", context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+            ))),
+            "noise" => Ok(Arc::new(NoiseTexture::new(
+                self.get_seed(table, context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+                self.parse_ramp(table, &[Vec3::zero(), Vec3::new(1.0, 1.0, 1.0)], context)?,
+            ))),
+            "turbulence" => Ok(Arc::new(TurbulenceTexture::new(
+                self.get_seed(table, context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+                self.get_octaves(table, context)?,
+                self.parse_ramp(table, &[Vec3::zero(), Vec3::new(1.0, 1.0, 1.0)], context)?,
+            ))),
+            "marble" => Ok(Arc::new(MarbleTexture::new(
+                self.get_seed(table, context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+                self.get_octaves(table, context)?,
+                self.get_number_or(table, "strength", 10.0, context)?,
+                self.parse_ramp(
+                    table,
+                    &[Vec3::new(0.2, 0.2, 0.25), Vec3::new(0.95, 0.95, 0.92)],
+                    context,
+                )?,
+            ))),
+            "wood" => Ok(Arc::new(WoodTexture::new(
+                self.get_seed(table, context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+                self.get_number_or(table, "strength", 0.5, context)?,
+                self.parse_ramp(
+                    table,
+                    &[Vec3::new(0.76, 0.56, 0.34), Vec3::new(0.45, 0.27, 0.13)],
+                    context,
+                )?,
+            ))),
+            other => Err(self.error(context, format!("unknown texture type '{}'", other))),
+        }
+    }
+
+    fn get_seed(&self, table: &Table, context: &str) -> Result<u64, LoadError> {
+        let seed = self.get_number_or(table, "seed", 0.0, context)?;
+        if seed < 0.0 || seed.fract() != 0.0 {
+            return Err(self.error(
+                context,
+                "seed must be a whole number of at least 0".to_string(),
+            ));
+        }
+        Ok(seed as u64)
+    }
+
+    fn get_octaves(&self, table: &Table, context: &str) -> Result<usize, LoadError> {
+        let octaves = self.get_number_or(table, "octaves", 7.0, context)?;
+        if octaves < 1.0 || octaves.fract() != 0.0 {
+            return Err(self.error(
+                context,
+                "octaves must be a whole number of at least 1".to_string(),
+            ));
+        }
+        Ok(octaves as usize)
+    }
+
+    // Colors spread evenly over the ramp unless positions are given for each of them
+    fn parse_ramp(
+        &self,
+        table: &Table,
+        default: &[Vec3],
+        context: &str,
+    ) -> Result<ColorRamp, LoadError> {
+        let colors_context = format!("{}.colors", context);
+        let colors = match table.get("colors") {
+            Some(value) => {
+                let values = value.as_array().ok_or_else(|| {
+                    self.error(&colors_context, "expected an array of colors".to_string())
+                })?;
+                if values.is_empty() {
+                    return Err(
+                        self.error(&colors_context, "expected at least one color".to_string())
+                    );
+                }
+                values
+                    .iter()
+                    .map(|v| self.vector(v, &colors_context))
+                    .collect::<Result<Vec<Vec3>, LoadError>>()?
+            }
+            None => default.to_vec(),
+        };
+        let positions_context = format!("{}.positions", context);
+        let positions = match table.get("positions") {
+            Some(value) => value
+                .as_array()
+                .ok_or_else(|| {
+                    self.error(
+                        &positions_context,
+                        "expected an array of numbers".to_string(),
+                    )
+                })?
+                .iter()
+                .map(|v| self.number(v, &positions_context))
+                .collect::<Result<Vec<Float>, LoadError>>()?,
+            None => return Ok(ColorRamp::even(&colors)),
+        };
+        if positions.len() != colors.len() {
+            return Err(self.error(
+                &positions_context,
+                format!("expected {} positions, one for each color", colors.len()),
+            ));
+        }
+        if positions.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(self.error(
+                &positions_context,
+                "positions must be in increasing order".to_string(),
+            ));
+        }
+        Ok(ColorRamp::new(positions.into_iter().zip(colors).collect()))
+    }
+
+    fn parse_material(
+        &self,
+        table: &Table,
+        context: &str,
+    ) -> Result<Arc<dyn BaseMaterial>, LoadError> {
+        match self.get_string(table, "type", context)? {
+            "lambertian" => Ok(Arc::new(Material {
+                albedo: self.get_texture(table, "albedo", context)?,
+            })),
+            "metal" => Ok(Arc::new(Metal::new(
+                self.get_texture(table, "albedo", context)?,
+                self.get_number_or(table, "fuzz", 0.0, context)?,
+            ))),
+            "dielectric" => {
+                let mut dielectric = Dielectric::new(self.get_number(table, "ior", context)?);
+                if let Some(absorption) = table.get("absorption") {
+                    dielectric.absorption =
+                        Some(self.vector(absorption, &format!("{}.absorption", context))?);
+                }
+                Ok(Arc::new(dielectric))
+            }
+            "conductor" => {
+                let roughness = self.get_number_or(table, "roughness", 0.0, context)?;
+                match table.get("metal") {
+                    Some(value) => {
+                        let name = self.string(value, &format!("{}.metal", context))?;
+                        let conductor = Conductor::from_name(name, roughness).ok_or_else(|| {
+                            self.error(context, format!("unknown metal '{}'", name))
+                        })?;
+                        Ok(Arc::new(conductor))
+                    }
+                    None => Ok(Arc::new(Conductor::new(
+                        self.get_vector(table, "eta", context)?,
+                        self.get_vector(table, "k", context)?,
+                        roughness,
+                    ))),
+                }
+            }
+            "rough_dielectric" => Ok(Arc::new(RoughDielectric::new(
+                self.get_number(table, "ior", context)?,
+                self.get_number_or(table, "roughness", 0.0, context)?,
+            ))),
+            "principled" => {
+                let mut principled = Principled::new(self.get_texture_or(
+                    table,
+                    "base_color",
+                    Vec3::new(0.8, 0.8, 0.8),
+                    context,
+                )?);
+                principled.metallic =
+                    self.get_scalar_texture_or(table, "metallic", 0.0, context)?;
+                principled.roughness =
+                    self.get_scalar_texture_or(table, "roughness", 0.5, context)?;
+                principled.specular =
+                    self.get_scalar_texture_or(table, "specular", 0.5, context)?;
+                principled.sheen = self.get_scalar_texture_or(table, "sheen", 0.0, context)?;
+                principled.sheen_tint =
+                    self.get_scalar_texture_or(table, "sheen_tint", 0.5, context)?;
+                principled.clearcoat =
+                    self.get_scalar_texture_or(table, "clearcoat", 0.0, context)?;
+                principled.clearcoat_roughness =
+                    self.get_scalar_texture_or(table, "clearcoat_roughness", 0.1, context)?;
+                principled.transmission =
+                    self.get_scalar_texture_or(table, "transmission", 0.0, context)?;
+                principled.ior = self.get_number_or(table, "ior", 1.5, context)?;
+                if table.contains_key("emission") {
+                    principled.emission = Some(self.get_texture(table, "emission", context)?);
+                }
+                Ok(Arc::new(principled))
+            }
+            "normal_map" => {
+                let map = self.get_texture(table, "texture", context)?;
+                if map.is_srgb_decoded() {
+                    return Err(self.error(
+                        &format!("{}.texture", context),
+                        "normal maps need srgb = false on their image texture".to_string(),
+                    ));
+                }
+                Ok(Arc::new(NormalMap::new(
+                    self.get_wrapped_material(table, context)?,
+                    map,
+                    self.get_number_or(table, "strength", 1.0, context)?,
+                )))
+            }
+            "bump_map" => Ok(Arc::new(BumpMap::new(
+                self.get_wrapped_material(table, context)?,
+                self.get_texture(table, "height", context)?,
+                self.get_number_or(table, "scale", 1.0, context)?,
+            ))),
+            "cutout" => {
+                // Stochastic cutouts keep every partial opacity unless a threshold is given
+                let stochastic = self.get_bool_or(table, "stochastic", false, context)?;
+                let threshold = if stochastic { 0.0 } else { 0.5 };
+                Ok(Arc::new(Cutout::new(
+                    self.get_wrapped_material(table, context)?,
+                    self.get_texture(table, "opacity", context)?,
+                    self.get_number_or(table, "threshold", threshold, context)?,
+                    stochastic,
+                )))
+            }
+            "light" => Ok(Arc::new(DiffuseLight {
+                emit: self.get_texture(table, "emit", context)?,
+            })),
+            other => Err(self.error(context, format!("unknown material type '{}'", other))),
+        }
+    }
+
+    // Applied as scale, then rotations around x, y and z in degrees, then translation
+    fn parse_transform(&self, value: &Value, context: &str) -> Result<Transform, LoadError> {
+        let table = self.table(value, context)?;
+        let mut transform = Transform::identity();
+        for key in table.keys() {
+            match key.as_str() {
+                "translate" | "rotate" | "scale" => {}
+                _ => return Err(self.error(context, format!("unknown transform '{}'", key))),
+            }
+        }
+        if let Some(scale) = table.get("scale") {
+            let scale_context = format!("{}.scale", context);
+            let factors = match scale {
+                Value::Array(_) => self.vector(scale, &scale_context)?,
+                _ => {
+                    let factor = self.number(scale, &scale_context)?;
+                    Vec3::new(factor, factor, factor)
+                }
+            };
+            if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+                return Err(self.error(&scale_context, "scale must not be zero".to_string()));
+            }
+            transform = Transform::scale(factors);
+        }
+        if let Some(rotate) = table.get("rotate") {
+            let angles = self.vector(rotate, &format!("{}.rotate", context))?;
+            transform = transform
+                .then(&Transform::rotate_x(angles.x))
+                .then(&Transform::rotate_y(angles.y))
+                .then(&Transform::rotate_z(angles.z));
+        }
+        if let Some(translate) = table.get("translate") {
+            let delta = self.vector(translate, &format!("{}.translate", context))?;
+            transform = transform.then(&Transform::translate(delta));
+        }
+        Ok(transform)
+    }
+
+    // An object moves from 'transform' at time0 to 'transform_end' at time1 when the latter is given
+    fn parse_placement(&self, table: &Table, context: &str) -> Result<Placement, LoadError> {
+        let start = match table.get("transform") {
+            Some(t) => Some(self.parse_transform(t, &format!("{}.transform", context))?),
+            None => None,
+        };
+        let end = match table.get("transform_end") {
+            Some(t) => self.parse_transform(t, &format!("{}.transform_end", context))?,
+            None => return Ok(start.map_or(Placement::Fixed, |t| Placement::Static(Box::new(t)))),
+        };
+        Ok(Placement::Animated(Box::new(AnimatedTransform::new(
+            start.unwrap_or_else(Transform::identity),
+            end,
+            self.get_number_or(table, "time0", 0.0, context)?,
+            self.get_number_or(table, "time1", 1.0, context)?,
+        ))))
+    }
+
+    fn place(&self, scene: &mut Scene, object: Arc<dyn Hittable>, placement: &Placement) {
+        match placement {
+            Placement::Fixed => scene.add_object(object),
+            Placement::Static(t) => {
+                scene.add_object(Arc::new(TransformedInstance::new(object, **t)))
+            }
+            Placement::Animated(t) => {
+                scene.add_object(Arc::new(AnimatedInstance::new(object, **t)))
+            }
+        }
+    }
+
+    fn place_shape(&self, scene: &mut Scene, shape: Object, placement: &Placement) {
+        match placement {
+            Placement::Fixed => scene.add_shape(shape),
+            _ => self.place(scene, Arc::new(shape), placement),
+        }
+    }
+
+    fn parse_object(
+        &mut self,
+        scene: &mut Scene,
+        table: &Table,
+        context: &str,
+    ) -> Result<(), LoadError> {
+        let placement = self.parse_placement(table, context)?;
+        match self.get_string(table, "type", context)? {
+            "sphere" => {
+                let sphere = Sphere::new(
+                    self.get_material(table, "material", context)?,
+                    self.get_number(table, "radius", context)?,
+                    self.get_vector(table, "center", context)?,
+                );
+                self.place_shape(scene, sphere, &placement);
+            }
+            "moving_sphere" => {
+                let sphere = MovingSphere::new(
+                    self.get_material(table, "material", context)?,
+                    self.get_number(table, "radius", context)?,
+                    self.get_vector(table, "center", context)?,
+                    self.get_vector(table, "center_end", context)?,
+                    self.get_number_or(table, "time0", 0.0, context)?,
+                    self.get_number_or(table, "time1", 1.0, context)?,
+                );
+                self.place(scene, Arc::new(sphere), &placement);
+            }
+            "triangle" => {
+                let vertices = match self.required(table, "vertices", context)?.as_array() {
+                    Some(v) if v.len() == 3 => v,
+                    _ => {
+                        return Err(self.error(context, "'vertices' must hold 3 points".to_string()))
+                    }
+                };
+                let vertex_context = format!("{}.vertices", context);
+                let triangle = Triangle::new(
+                    self.get_material(table, "material", context)?,
+                    self.vector(&vertices[0], &vertex_context)?,
+                    self.vector(&vertices[1], &vertex_context)?,
+                    self.vector(&vertices[2], &vertex_context)?,
+                );
+                self.place_shape(scene, triangle, &placement);
+            }
+            "mesh" => {
+                for mesh in self.load_mesh(table, context)? {
+                    self.place(scene, mesh, &placement);
+                }
+            }
+            "medium" => {
+                let density = self.get_number(table, "density", context)?;
+                if density <= 0.0 {
+                    let message = "must be positive".to_string();
+                    return Err(self.error(&format!("{}.density", context), message));
+                }
+                let albedo = self.get_texture(table, "albedo", context)?;
+                let boundary_context = format!("{}.boundary", context);
+                let boundary = self.parse_boundary(
+                    self.table(
+                        self.required(table, "boundary", context)?,
+                        &boundary_context,
+                    )?,
+                    &boundary_context,
+                    &albedo,
+                )?;
+                let medium = ConstantMedium::new(boundary, density, albedo);
+                self.place(scene, Arc::new(medium), &placement);
+            }
+            other => return Err(self.error(context, format!("unknown object type '{}'", other))),
+        }
+        Ok(())
+    }
+
+    fn load_mesh(
+        &mut self,
+        table: &Table,
+        context: &str,
+    ) -> Result<Vec<Arc<dyn Hittable>>, LoadError> {
+        let file = self.base_dir.join(self.get_string(table, "file", context)?);
+        let material = match table.get("material") {
+            Some(m) => Some(self.string(m, &format!("{}.material", context))?),
+            None => None,
+        };
+        let key = (file.clone(), material.map(|m| m.to_string()));
+        if !self.meshes.contains_key(&key) {
+            let meshes = match table.get("material") {
+                Some(m) => load_obj_with_material(
+                    &file,
+                    self.material(m, &format!("{}.material", context))?,
+                )?,
+                None => load_obj(&file)?,
+            };
+            self.meshes.insert(key.clone(), meshes);
+        }
+        Ok(self.meshes[&key].clone())
+    }
+
+    // Closed shape enclosing a medium, its material is never shaded
+    fn parse_boundary(
+        &mut self,
+        table: &Table,
+        context: &str,
+        albedo: &Arc<dyn Texture>,
+    ) -> Result<Arc<dyn Hittable>, LoadError> {
+        match self.get_string(table, "type", context)? {
+            "sphere" => Ok(Arc::new(Sphere::new(
+                Arc::new(Isotropic::new(albedo.clone())),
+                self.get_number(table, "radius", context)?,
+                self.get_vector(table, "center", context)?,
+            ))),
+            "mesh" => Ok(Arc::new(Bvh::new(&self.load_mesh(table, context)?))),
+            other => Err(self.error(context, format!("unknown boundary type '{}'", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<SceneFile, LoadError> {
+        parse_scene_file(source, Path::new("test.toml"))
+    }
+
+    #[test]
+    fn declarations_may_refer_to_later_ones() {
+        let scene = parse(
+            r#"
+            [textures.checker]
+            type = "checker"
+            even = "white"
+            odd = [0.0, 0.0, 0.0]
+
+            [textures.white]
+            type = "constant"
+            color = [1.0, 1.0, 1.0]
+
+            [materials.a_bump]
+            type = "bump_map"
+            material = "m_cut"
+            height = "white"
+
+            [materials.m_cut]
+            type = "cutout"
+            material = "z_base"
+            opacity = "checker"
+
+            [materials.z_base]
+            type = "lambertian"
+            albedo = "checker"
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = "a_bump"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scene.textures, vec!["checker", "white"]);
+        assert_eq!(scene.materials, vec!["a_bump", "m_cut", "z_base"]);
+    }
+
+    #[test]
+    fn cyclic_declarations_are_rejected() {
+        let error = parse(
+            r#"
+            [materials.a]
+            type = "cutout"
+            material = "b"
+            opacity = [1.0, 1.0, 1.0]
+
+            [materials.b]
+            type = "normal_map"
+            material = "a"
+            texture = [0.5, 0.5, 1.0]
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert!(error.to_string().contains("material 'a' refers to itself"));
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let error = parse(
+            r#"
+            [textures.checker]
+            type = "checker"
+            even = "missing"
+            odd = [0.0, 0.0, 0.0]
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .contains("textures.checker.even: unknown texture 'missing'"));
+    }
+}
@@ -1,76 +1,184 @@
 // Main File
 use rustracer::{
-    gen_random_sphere, get_color, get_metal, Float, ImageBuilder, PixelData, Scene, Sphere, Hittable,
-    ThreadPool, Vec3,
+    load_scene_file, render, AcesFilmic, Exposure, ExrCompression, ExrPixelType, ExrWriter,
+    ImageWriter, PfmWriter, PngWriter, PpmWriter, Reinhard, RenderSettings, SamplerType, ToneMapper,
+    Uncharted2,
 };
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::sync::{mpsc, Arc, Mutex};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
 
-fn get_final_color(
-    i: usize,
-    j: usize,
-    width: usize,
-    height: usize,
-    scene: Rc<Scene>,
-    builder: Rc<RefCell<ImageBuilder>>,
-    passes: usize,
-    pool: &ThreadPool,
-) {
-    // let accel = Rc::new(scene.accelerator.unwrap());
-    let accel = scene.accelerator.unwrap();
-    let camera = scene.camera.clone();
-    let rap_w: Float = 1.0 / width as Float;
-    let rap_h: Float = 1.0 / width as Float;
-    for _ in 0..passes {
-        pool.execute(|ac: Rc<dyn Hittable>| {
-            let rand_x: Float = rand::random::<Float>() * rap_w - rap_w / 2.0;
-            let rand_y: Float = rand::random::<Float>() * rap_h - rap_h / 2.0;
-            let ray = camera.get_origin_ray(
-                (width as Float) / (height as Float),
-                i as Float / width as Float + rand_x,
-                j as Float / height as Float + rand_y,
-            );
-            let color = get_color(&ray, ac, 0);
-            PixelData {
-                pixel: color,
-                x: i,
-                y: j,
+const USAGE: &str = "Usage:
+  rustracer render <scene.toml> [options]
+  rustracer info <scene.toml>
+
+Render options:
+  -o, --output <file>     output image, format from the extension: png, ppm, pfm, exr (default: out.png)
+  --width <pixels>        image width (default: 640)
+  --height <pixels>       image height (default: 480)
+  --spp <count>           samples per pixel (default: 100)
+  --threads <count>       worker threads (default: available cores)
+  --seed <number>         random seed (default: 0)
+  --max-depth <bounces>   maximum path depth (default: 50)
+  --sampler <type>        independent, stratified, halton or sobol (default: independent)
+  --tonemap <operator>    clamp, reinhard, aces or uncharted2 for png and ppm output (default: clamp)
+  --exposure <stops>      exposure applied before tone mapping (default: 0)";
+
+struct RenderOptions {
+    scene: PathBuf,
+    output: PathBuf,
+    settings: RenderSettings,
+    tonemap: String,
+    exposure: f64,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+fn usage_error(message: String) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    let value = value.unwrap_or_else(|| usage_error(format!("{} needs a value", flag)));
+    value
+        .parse()
+        .unwrap_or_else(|_| usage_error(format!("invalid value '{}' for {}", value, flag)))
+}
+
+fn parse_render_options(args: Vec<String>) -> RenderOptions {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut settings = RenderSettings::new(640, 480, 100, threads);
+    let mut scene: Option<PathBuf> = None;
+    let mut output = PathBuf::from("out.png");
+    let mut tonemap = "clamp".to_string();
+    let mut exposure = 0.0;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = PathBuf::from(parse_value::<String>(&arg, args.next())),
+            "--width" => settings.width = parse_value(&arg, args.next()),
+            "--height" => settings.height = parse_value(&arg, args.next()),
+            "--spp" => settings.passes = parse_value(&arg, args.next()),
+            "--threads" => settings.threads = parse_value(&arg, args.next()),
+            "--seed" => settings.seed = parse_value(&arg, args.next()),
+            "--max-depth" => settings.max_depth = parse_value(&arg, args.next()),
+            "--sampler" => {
+                let name: String = parse_value(&arg, args.next());
+                settings.sampler = SamplerType::from_name(&name)
+                    .unwrap_or_else(|| usage_error(format!("unknown sampler '{}'", name)));
             }
-        }, accel);
+            "--tonemap" => tonemap = parse_value(&arg, args.next()),
+            "--exposure" => exposure = parse_value(&arg, args.next()),
+            _ if arg.starts_with('-') => usage_error(format!("unknown option '{}'", arg)),
+            _ if scene.is_none() => scene = Some(PathBuf::from(arg)),
+            _ => usage_error(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    if settings.width == 0 || settings.height == 0 || settings.passes == 0 || settings.threads == 0
+    {
+        usage_error("width, height, spp and threads must be positive".to_string());
+    }
+    RenderOptions {
+        scene: scene.unwrap_or_else(|| usage_error("missing scene file".to_string())),
+        output,
+        settings,
+        tonemap,
+        exposure,
     }
 }
 
-fn main() {
-    let width = 640;
-    let height = 480;
-    let sphere_plane = Rc::new(Sphere::new(get_metal(), 100.0, Vec3::new(0.0, -100.0, 5.0)));
-    let mut scene = Scene::new();
-    scene.add_object(sphere_plane);
-    for _ in 0..10 {
-        let obj = gen_random_sphere();
-        scene.add_object(obj);
+fn tone_mapper(name: &str, exposure: f64) -> Box<dyn ToneMapper> {
+    match name {
+        "clamp" => Box::new(Exposure::new(exposure)),
+        "reinhard" => Box::new(Reinhard::new(exposure)),
+        "aces" => Box::new(AcesFilmic::new(exposure)),
+        "uncharted2" => Box::new(Uncharted2::new(exposure)),
+        _ => usage_error(format!("unknown tone mapping operator '{}'", name)),
     }
-    scene.build_accelerator();
-    let scene = Rc::new(scene);
-    let passes: usize = 100;
-    let image_builder = Rc::new(RefCell::new(ImageBuilder::new(width, height, passes)));
-    let (sender, receiver) = mpsc::channel::<PixelData>();
-    let thread_pool = ThreadPool::new(10, Arc::new(Mutex::new(sender)));
-    for i in 0..width {
-        for j in 0..height {
-            get_final_color(
-                i,
-                j,
-                width,
-                height,
-                scene.clone(),
-                image_builder.clone(),
-                passes,
-                &thread_pool,
-            );
-        }
+}
+
+fn image_writer(options: &RenderOptions) -> Box<dyn ImageWriter> {
+    let extension = options
+        .output
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("png") => Box::new(PngWriter::with_tone_mapper(tone_mapper(
+            &options.tonemap,
+            options.exposure,
+        ))),
+        Some("ppm") => Box::new(PpmWriter::with_tone_mapper(tone_mapper(
+            &options.tonemap,
+            options.exposure,
+        ))),
+        Some("pfm") => Box::new(PfmWriter::new()),
+        Some("exr") => Box::new(ExrWriter::new(ExrPixelType::Half, ExrCompression::Zip)),
+        _ => usage_error(format!(
+            "unsupported output format for {}",
+            options.output.display()
+        )),
+    }
+}
+
+fn render_command(args: Vec<String>) {
+    let options = parse_render_options(args);
+    let writer = image_writer(&options);
+    let file = load_scene_file(&options.scene).unwrap_or_else(|e| fail(e.to_string()));
+    let start = Instant::now();
+    let image = render(Arc::new(file.scene), &options.settings)
+        .unwrap_or_else(|e| fail(e))
+        .to_image();
+    writer
+        .write(&image, &options.output)
+        .unwrap_or_else(|e| fail(format!("cannot write {}: {}", options.output.display(), e)));
+    eprintln!(
+        "Rendered {}x{} at {} spp in {:.2}s to {}",
+        options.settings.width,
+        options.settings.height,
+        options.settings.passes,
+        start.elapsed().as_secs_f64(),
+        options.output.display()
+    );
+}
+
+fn info_command(args: Vec<String>) {
+    if args.len() != 1 {
+        usage_error("info takes exactly one scene file".to_string());
+    }
+    let path = Path::new(&args[0]);
+    let file = load_scene_file(path).unwrap_or_else(|e| fail(e.to_string()));
+    let scene = &file.scene;
+    println!("Scene {}", path.display());
+    println!(" Objects: {}", scene.objects.len());
+    println!(" Primitives: {}", scene.primitive_count());
+    println!(" Textures: {}", file.textures.len());
+    println!(" Materials: {}", file.materials.len());
+    println!("{}", scene.camera);
+    println!("{}", scene.environment.to_string());
+    if let Some(fog) = &scene.fog {
+        println!("{}", fog);
+    }
+    if let Some(bvh) = &scene.accelerator {
+        println!("{}", bvh.stats());
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = args.next();
+    let rest: Vec<String> = args.collect();
+    match command.as_deref() {
+        Some("render") => render_command(rest),
+        Some("info") => info_command(rest),
+        Some("-h") | Some("--help") | Some("help") => println!("{}", USAGE),
+        Some(other) => usage_error(format!("unknown command '{}'", other)),
+        None => usage_error("missing command".to_string()),
     }
-    let img = image_builder.borrow().to_image();
-    println!("P3 \n{} {}\n255\n{}", width, height, img.to_string());
 }
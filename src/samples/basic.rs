@@ -1,21 +1,22 @@
 use crate::{
-    BaseMaterial, ConstantTexture, DiffuseLight, Float, Hittable, Material, Metal, Sphere, Vec3,
+    BaseMaterial, ConstantTexture, DiffuseLight, Float, Material, Metal, Object, Pcg32, Sphere,
+    Vec3,
 };
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub fn basic_scene() {}
-pub fn gen_random_material() -> Rc<dyn BaseMaterial> {
-    let color = Vec3::random_unit();
-    let value: Float = rand::random();
+pub fn gen_random_material(rng: &mut Pcg32) -> Arc<dyn BaseMaterial> {
+    let color = Vec3::random_unit(rng);
+    let value: Float = rng.uniform();
     let rap: Float = 1.0 / 3.0;
     if value < rap {
-        return Rc::new(Material::new(color));
+        return Arc::new(Material::new(color));
     } else if value > rap * 8.0 {
-        return Rc::new(DiffuseLight::new(color * 100.0));
+        return Arc::new(DiffuseLight::new(color * 100.0));
     }
-    let fuzz = rand::random();
-    Rc::new(Metal::new(
-        Rc::new(ConstantTexture {
+    let fuzz = rng.uniform();
+    Arc::new(Metal::new(
+        Arc::new(ConstantTexture {
             // color: Vec3::new(1.0, 1.0, 1.0),
             color,
         }),
@@ -23,18 +24,18 @@ pub fn gen_random_material() -> Rc<dyn BaseMaterial> {
     ))
 }
 
-pub fn gen_random_sphere() -> Rc<dyn Hittable> {
-    let radius = rand::random::<Float>() * 0.3;
-    let mut position: Vec3 = Vec3::random_unit();
+pub fn gen_random_sphere(rng: &mut Pcg32) -> Object {
+    let radius = rng.uniform() * 0.3;
+    let mut position: Vec3 = Vec3::random_unit(rng);
     position = position * 5.0;
     position.y = radius;
-    let material = gen_random_material();
-    Rc::new(Sphere::new(material, radius, position))
+    let material = gen_random_material(rng);
+    Sphere::new(material, radius, position)
 }
 
-pub fn get_metal() -> Rc<Metal> {
-    Rc::new(Metal::new(
-        Rc::new(ConstantTexture {
+pub fn get_metal() -> Arc<Metal> {
+    Arc::new(Metal::new(
+        Arc::new(ConstantTexture {
             color: Vec3::new(1.0, 1.0, 1.0),
         }),
         0.05,
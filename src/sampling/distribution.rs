@@ -0,0 +1,98 @@
+// Piecewise constant distributions
+use crate::Float;
+
+// Density proportional to a step function over [0, 1) with one step per value
+pub struct Distribution1D {
+    pub func: Vec<Float>,
+    pub cdf: Vec<Float>,
+    pub integral: Float,
+}
+
+// Density over [0, 1)^2 proportional to a grid of values, sampled as a marginal
+// distribution over rows followed by the conditional distribution of the chosen row
+pub struct Distribution2D {
+    pub conditional: Vec<Distribution1D>,
+    pub marginal: Distribution1D,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[Float]) -> Distribution1D {
+        let n = func.len();
+        let func: Vec<Float> = func.iter().map(|f| f.abs()).collect();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as Float;
+        }
+        let integral = cdf[n];
+        // A function that is zero everywhere is sampled uniformly
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as Float / n as Float
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    // Returns the sampled position, its density and the index of its step
+    pub fn sample_continuous(&self, u: Float) -> (Float, Float, usize) {
+        let n = self.count();
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as Float + du) / n as Float).min(1.0 - Float::EPSILON);
+        (x, self.density(offset), offset)
+    }
+
+    // Density of the step at index
+    pub fn density(&self, index: usize) -> Float {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Distribution2D {
+    // Values are given row by row, width values for each of the height rows
+    pub fn new(func: &[Float], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(Distribution1D::new)
+            .collect();
+        let marginal: Vec<Float> = conditional.iter().map(|d| d.integral).collect();
+        Distribution2D {
+            conditional,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    // Returns a point in the unit square, x along rows and y across them, with its density
+    pub fn sample(&self, u: (Float, Float)) -> ((Float, Float), Float) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: Float, y: Float) -> Float {
+        let row = ((y * self.marginal.count() as Float) as usize).min(self.marginal.count() - 1);
+        let conditional = &self.conditional[row];
+        let column = ((x * conditional.count() as Float) as usize).min(conditional.count() - 1);
+        self.marginal.density(row) * conditional.density(column)
+    }
+}
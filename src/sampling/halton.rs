@@ -0,0 +1,84 @@
+// Halton sampler
+use crate::{hash_values, to_unit_float, Float, Pcg32, Sampler};
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// Radical inverse of index in the given base, its digits mirrored around the decimal point
+pub fn radical_inverse(base: u64, mut index: u64) -> Float {
+    let inverse_base = 1.0 / base as Float;
+    let mut reversed: u64 = 0;
+    let mut inverse_base_power = 1.0;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inverse_base_power *= inverse_base;
+        index = next;
+    }
+    (reversed as Float * inverse_base_power).min(1.0 - Float::EPSILON / 2.0)
+}
+
+// Every pixel walks the Halton sequence from its start, decorrelated from its neighbours
+// by a random toroidal shift of each dimension (Cranley-Patterson rotation). Dimensions
+// past the prime table fall back to independent numbers.
+pub struct HaltonSampler {
+    pub seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: usize,
+    rng: Pcg32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash_values(&[self.seed, x as u64, y as u64]), index as u64);
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.rng.uniform();
+        }
+        let shift = hash_values(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            dimension as u64,
+        ]);
+        let value = radical_inverse(PRIMES[dimension], self.index as u64)
+            + to_unit_float((shift >> 32) as u32);
+        if value >= 1.0 {
+            value - 1.0
+        } else {
+            value
+        }
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn to_string(&self) -> String {
+        format!("Halton sampler with seed {}", self.seed)
+    }
+}
@@ -0,0 +1,34 @@
+// Independent sampler
+use crate::{hash_values, Float, Pcg32, Sampler};
+
+pub struct IndependentSampler {
+    pub seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = Pcg32::new(hash_values(&[self.seed, x as u64, y as u64]), index as u64);
+    }
+
+    fn get_1d(&mut self) -> Float {
+        self.rng.uniform()
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.rng.uniform(), self.rng.uniform())
+    }
+
+    fn to_string(&self) -> String {
+        format!("Independent sampler with seed {}", self.seed)
+    }
+}
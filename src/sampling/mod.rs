@@ -0,0 +1,17 @@
+// Sampling
+mod distribution;
+mod halton;
+mod independent;
+mod random;
+mod sampler;
+mod sobol;
+mod stratified;
+mod warp;
+pub use distribution::*;
+pub use halton::*;
+pub use independent::*;
+pub use random::*;
+pub use sampler::*;
+pub use sobol::*;
+pub use stratified::*;
+pub use warp::*;
@@ -0,0 +1,44 @@
+// Samplers
+use crate::{Float, HaltonSampler, IndependentSampler, SobolSampler, StratifiedSampler};
+
+// Source of the uniform numbers in [0, 1) consumed while tracing one sample of a pixel.
+// Every call moves to the next dimension of the sample's sequence, so camera, materials
+// and lights should always request their numbers in the same order.
+pub trait Sampler: Send {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+    fn get_1d(&mut self) -> Float;
+    fn get_2d(&mut self) -> (Float, Float);
+    fn to_string(&self) -> String;
+    fn print(&self) {
+        println!("{}", self.to_string());
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SamplerType {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerType {
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name {
+            "independent" => Some(SamplerType::Independent),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn create(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// Sobol sampler
+use crate::{hash_values, permutation_element, to_unit_float, Float, Sampler};
+
+// The first two dimensions of the Sobol sequence, the second one from the generator
+// matrix of the polynomial x + 1
+pub fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut column: u32 = 1 << 31;
+    let mut second: u32 = 0;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            second ^= column;
+        }
+        bits >>= 1;
+        column ^= column >> 1;
+    }
+    (index.reverse_bits(), second)
+}
+
+// Hash based nested uniform scrambling (Laine and Karras 2011), every bit is flipped
+// depending on the bits above it
+pub fn owen_scramble(value: u32, seed: u32) -> u32 {
+    let mut v = value.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20_adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6c56);
+    v ^= v.wrapping_mul(0x53a2_2864);
+    v.reverse_bits()
+}
+
+// Padded Owen scrambled Sobol samples: each pair of dimensions takes the well distributed
+// first two Sobol dimensions, scrambled and shuffled across the samples of the pixel
+// independently, which keeps the stratification of the pairs in every dimension.
+pub struct SobolSampler {
+    pub samples_per_pixel: usize,
+    pub seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> SobolSampler {
+        SobolSampler {
+            samples_per_pixel,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let hash = hash_values(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension,
+        ]);
+        self.dimension += 1;
+        let index = if self.index < self.samples_per_pixel {
+            permutation_element(
+                self.index as u32,
+                self.samples_per_pixel as u32,
+                hash as u32,
+            )
+        } else {
+            self.index as u32
+        };
+        (index, hash)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let (index, hash) = self.next_dimension();
+        to_unit_float(owen_scramble(index.reverse_bits(), (hash >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let (index, hash) = self.next_dimension();
+        let (x, y) = sobol_2d(index);
+        (
+            to_unit_float(owen_scramble(x, (hash >> 32) as u32)),
+            to_unit_float(owen_scramble(y, hash.rotate_left(16) as u32)),
+        )
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Owen scrambled Sobol sampler with {} samples per pixel and seed {}",
+            self.samples_per_pixel, self.seed
+        )
+    }
+}
@@ -0,0 +1,87 @@
+// Stratified sampler
+use crate::{hash_values, permutation_element, Float, Pcg32, Sampler};
+
+// Splits every dimension in as many strata as there are samples per pixel and jitters
+// the sample inside its stratum. Pairs of dimensions use a square grid when the sample
+// count allows one and Latin hypercube strata otherwise, the strata of each dimension
+// are visited in a different random order per pixel.
+pub struct StratifiedSampler {
+    pub samples_per_pixel: usize,
+    pub seed: u64,
+    grid: usize,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> StratifiedSampler {
+        let root = (samples_per_pixel as Float).sqrt().round() as usize;
+        StratifiedSampler {
+            samples_per_pixel,
+            seed,
+            grid: if root * root == samples_per_pixel {
+                root
+            } else {
+                0
+            },
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    fn stratum(&mut self) -> usize {
+        let hash = hash_values(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension,
+        ]);
+        self.dimension += 1;
+        if self.index >= self.samples_per_pixel {
+            return self.index % self.samples_per_pixel;
+        }
+        permutation_element(
+            self.index as u32,
+            self.samples_per_pixel as u32,
+            hash as u32,
+        ) as usize
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash_values(&[self.seed, x as u64, y as u64]), index as u64);
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let stratum = self.stratum();
+        (stratum as Float + self.rng.uniform()) / self.samples_per_pixel as Float
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        if self.grid > 0 {
+            let stratum = self.stratum();
+            self.dimension += 1;
+            let n = self.grid as Float;
+            return (
+                ((stratum % self.grid) as Float + self.rng.uniform()) / n,
+                ((stratum / self.grid) as Float + self.rng.uniform()) / n,
+            );
+        }
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "Stratified sampler with {} samples per pixel and seed {}",
+            self.samples_per_pixel, self.seed
+        )
+    }
+}
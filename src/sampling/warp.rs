@@ -0,0 +1,34 @@
+// Mappings from the unit square
+use crate::{Float, Vec3};
+use std::f64::consts::PI;
+
+pub fn sample_unit_sphere(u: (Float, Float)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Concentric mapping (Shirley and Chiu), keeps the strata of the square compact on the disk
+pub fn sample_unit_disk(u: (Float, Float)) -> (Float, Float) {
+    let x = 2.0 * u.0 - 1.0;
+    let y = 2.0 * u.1 - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, PI / 4.0 * (y / x))
+    } else {
+        (y, PI / 2.0 - PI / 4.0 * (x / y))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+// Uniformly distributed over the cone of directions within theta_max of +z, the cone is
+// given by 1 - cos(theta_max) to keep its precision for narrow cones
+pub fn sample_uniform_cone(u: (Float, Float), one_minus_cos_max: Float) -> Vec3 {
+    let cos_theta = 1.0 - u.0 * one_minus_cos_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
@@ -1,3 +1,5 @@
 // Threading
+mod renderer;
 mod threadpool;
+pub use renderer::*;
 pub use threadpool::*;
@@ -0,0 +1,209 @@
+// Tile renderer
+use crate::{get_color, Float, ImageBuilder, SamplerType, Scene, ThreadPool, Vec3};
+use std::sync::{mpsc, Arc};
+
+#[derive(Copy, Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub passes: usize,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub max_depth: i32,
+    pub seed: u64,
+    pub sampler: SamplerType,
+}
+
+impl RenderSettings {
+    pub fn new(width: usize, height: usize, passes: usize, threads: usize) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            passes,
+            threads,
+            tile_size: 16,
+            max_depth: 50,
+            seed: 0,
+            sampler: SamplerType::Independent,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> Float {
+        self.width as Float / self.height as Float
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Radiance of every pixel of a tile summed over all passes, stored row by row
+pub struct TileData {
+    pub tile: Tile,
+    pub pixels: Vec<Vec3>,
+    pub passes: usize,
+}
+
+pub fn split_tiles(width: usize, height: usize, tile_size: usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size) {
+        for x in (0..width).step_by(tile_size) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+pub fn render_tile(scene: &Scene, tile: &Tile, settings: &RenderSettings) -> TileData {
+    // Sample sequences only depend on the seed, the pixel and the pass, never on the tile
+    // or the thread rendering it, so a seed always reproduces the same image
+    let mut sampler = settings.sampler.create(settings.passes, settings.seed);
+    let mut pixels = Vec::with_capacity(tile.width * tile.height);
+    let aspect_ratio = settings.aspect_ratio();
+    for j in tile.y..tile.y + tile.height {
+        for i in tile.x..tile.x + tile.width {
+            let mut color = Vec3::zero();
+            for pass in 0..settings.passes {
+                sampler.start_pixel_sample(i, j, pass);
+                let (dx, dy) = sampler.get_2d();
+                let x = (i as Float + dx) / settings.width as Float;
+                let y = (j as Float + dy) / settings.height as Float;
+                let ray = scene
+                    .camera
+                    .get_origin_ray(aspect_ratio, x, y, sampler.as_mut());
+                color = color + get_color(&ray, scene, sampler.as_mut(), 0, settings.max_depth);
+            }
+            pixels.push(color);
+        }
+    }
+    TileData {
+        tile: *tile,
+        pixels,
+        passes: settings.passes,
+    }
+}
+
+// Fails when a tile job panicked, rather than returning an image with holes in it
+pub fn render(scene: Arc<Scene>, settings: &RenderSettings) -> Result<ImageBuilder, String> {
+    let pool = ThreadPool::new(settings.threads);
+    let (sender, receiver) = mpsc::channel::<TileData>();
+    let tiles = split_tiles(settings.width, settings.height, settings.tile_size);
+    let count = tiles.len();
+    for tile in tiles {
+        let scene = scene.clone();
+        let sender = sender.clone();
+        let settings = *settings;
+        pool.execute(move || {
+            let data = render_tile(&scene, &tile, &settings);
+            sender.send(data).unwrap();
+        });
+    }
+    // Only the jobs hold senders now, so receiving ends once every job has finished or panicked
+    drop(sender);
+    let mut builder = ImageBuilder::new(settings.width, settings.height, settings.passes);
+    let mut received = 0;
+    for data in receiver.iter().take(count) {
+        builder.put_tile(&data);
+        received += 1;
+    }
+    if received < count {
+        return Err(format!(
+            "{} of {} tiles failed to render",
+            count - received,
+            count
+        ));
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_scene, Image};
+    use std::path::Path;
+
+    const SCENE: &str = r#"
+        [camera]
+        position = [0.0, 1.0, -4.0]
+        look_at = [0.0, 0.5, 0.0]
+
+        [fog]
+        density = 0.05
+        albedo = [0.8, 0.8, 0.8]
+
+        [materials.floor]
+        type = "lambertian"
+        albedo = [0.5, 0.5, 0.5]
+
+        [materials.glass]
+        type = "dielectric"
+        ior = 1.5
+
+        [materials.lamp]
+        type = "light"
+        emit = [4.0, 4.0, 4.0]
+
+        [[objects]]
+        type = "sphere"
+        center = [0.0, -100.0, 0.0]
+        radius = 100.0
+        material = "floor"
+
+        [[objects]]
+        type = "sphere"
+        center = [0.0, 0.5, 0.0]
+        radius = 0.5
+        material = "glass"
+
+        [[objects]]
+        type = "sphere"
+        center = [1.0, 2.0, 0.0]
+        radius = 0.3
+        material = "lamp"
+        "#;
+
+    fn render_image(scene: &Arc<Scene>, settings: &RenderSettings) -> Image {
+        render(scene.clone(), settings).unwrap().to_image()
+    }
+
+    fn pixels(image: &Image) -> Vec<(Float, Float, Float)> {
+        image
+            .pixels
+            .iter()
+            .flatten()
+            .map(|p| (p.x, p.y, p.z))
+            .collect()
+    }
+
+    #[test]
+    fn renders_do_not_depend_on_threads_or_tiles() {
+        let scene = Arc::new(parse_scene(SCENE, Path::new("test.toml")).unwrap());
+        let samplers = [
+            SamplerType::Independent,
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ];
+        for sampler in samplers.iter() {
+            let mut settings = RenderSettings::new(24, 16, 4, 1);
+            settings.max_depth = 8;
+            settings.seed = 5;
+            settings.sampler = *sampler;
+            let reference = render_image(&scene, &settings);
+            settings.threads = 3;
+            settings.tile_size = 5;
+            assert_eq!(pixels(&render_image(&scene, &settings)), pixels(&reference));
+            settings.seed = 6;
+            assert_ne!(pixels(&render_image(&scene, &settings)), pixels(&reference));
+        }
+    }
+}
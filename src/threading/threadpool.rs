@@ -1,99 +1,101 @@
 // Thread Pool
-use crate::{BoundingBoxNode, Hittable, Vec3};
-use std::rc::Rc;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
 struct Worker {
-    id: usize,
     thread: Option<thread::JoinHandle<()>>,
 }
-struct Job {
-    fun: Box<dyn FnBox + Send + 'static>,
-    arg: Arc<BoundingBoxNode>,
-}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
 
 enum Message {
     NewJob(Job),
     Terminate,
 }
 
-trait FnBox {
-    fn call_box(self: Box<Self>, a: Arc<BoundingBoxNode>) -> PixelData;
-}
-
-pub struct PixelData {
-    pub pixel: Vec3,
-    pub x: usize,
-    pub y: usize,
-}
-
-impl<F: FnOnce(Arc<BoundingBoxNode>) -> PixelData> FnBox for F {
-    fn call_box(self: Box<F>, a: Arc<BoundingBoxNode>) -> PixelData {
-        (*self)(a)
-    }
-}
-
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: mpsc::Sender<Message>,
 }
 
 impl Worker {
-    fn new(
-        id: usize,
-        receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
-        sender: Arc<Mutex<mpsc::Sender<PixelData>>>,
-    ) -> Worker {
-        let thread = thread::spawn(move || loop {
-            let message = receiver.lock().unwrap().recv().unwrap();
-            match message {
-                Message::NewJob(job) => {
-                    let result = job.fun.call_box(job.arg);
-                    sender.lock().unwrap().send(result).unwrap();
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
+        let thread = thread::Builder::new()
+            .name(format!("worker-{}", id))
+            .spawn(move || loop {
+                let message = receiver.lock().unwrap().recv().unwrap();
+                match message {
+                    Message::NewJob(job) => {
+                        // The panic hook has already reported a panicking job, and the worker
+                        // has to live on to run the jobs still queued
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    Message::Terminate => {
+                        break;
+                    }
                 }
-                Message::Terminate => {
-                    break;
-                }
-            }
-        });
+            })
+            .unwrap();
         Worker {
-            id,
             thread: Some(thread),
         }
     }
 }
 impl ThreadPool {
-    pub fn new(size: usize, pixel_sender: Arc<Mutex<mpsc::Sender<PixelData>>>) -> ThreadPool {
+    pub fn new(size: usize) -> ThreadPool {
         assert!(size > 0);
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
         let mut workers = Vec::with_capacity(size);
         for i in 0..size {
-            workers.push(Worker::new(i, receiver.clone(), pixel_sender.clone()));
+            workers.push(Worker::new(i, receiver.clone()));
         }
         ThreadPool { workers, sender }
     }
-    pub fn execute<F>(&self, f: F, a: Rc<BoundingBoxNode>)
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn execute<F>(&self, f: F)
     where
-        F: FnOnce(Rc<BoundingBoxNode>) -> PixelData + Send + 'static,
+        F: FnOnce() + Send + 'static,
     {
-        let job = Job {
-            fun: Box::new(f),
-            arg: Arc::new(BoundingBoxNode::zero()),
-        };
-        self.sender.send(Message::NewJob(job)).unwrap();
+        self.sender.send(Message::NewJob(Box::new(f))).unwrap();
     }
 }
 impl Drop for ThreadPool {
     fn drop(&mut self) {
+        // Workers outlive panicking jobs, so a failed send or join is only ignored here
         for _ in &mut self.workers {
-            self.sender.send(Message::Terminate).unwrap();
+            let _ = self.sender.send(Message::Terminate);
         }
         for worker in &mut self.workers {
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                let _ = thread.join();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn jobs_after_a_panicking_job_still_run() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        let failing = sender.clone();
+        pool.execute(move || {
+            let _sender = failing;
+            panic!("job failed");
+        });
+        pool.execute(move || sender.send(1).unwrap());
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(1));
+        // Both senders are gone once the jobs are done, panicking or not
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).is_err());
+    }
+}
@@ -31,6 +31,25 @@ pub fn to_color(a: Float, start: Float, end: Float) -> i32 {
     clamp(map(a, start, end, 0.0, 255.0), 0.0, 255.0) as i32
 }
 
+pub fn to_byte(a: Float) -> u8 {
+    (clamp(a, 0.0, 1.0) * 255.0).round() as u8
+}
+
+pub fn linear_to_srgb(a: Float) -> Float {
+    let a = clamp(a, 0.0, 1.0);
+    if a <= 0.003_130_8 {
+        return 12.92 * a;
+    }
+    1.055 * a.powf(1.0 / 2.4) - 0.055
+}
+
+pub fn srgb_to_linear(a: Float) -> Float {
+    if a <= 0.040_45 {
+        return a / 12.92;
+    }
+    ((a + 0.055) / 1.055).powf(2.4)
+}
+
 pub fn sigmoid(f: Float) -> Float {
     use std::f64::consts::E;
     1.0 / (1.0 + E.powf(-f))
@@ -1,3 +1,3 @@
-rm test.ppm
-cargo run >> test.ppm
-eog test.ppm
+rm -f test.png
+cargo run --release -- render scenes/basic.toml -o test.png
+eog test.png